@@ -81,7 +81,7 @@ fn main() {
     };
 
     // sampling the noise and generating our tessellation
-    let field = Field::new_from_fun(tile_x, tile_y, res, noise);
+    let field = Field::new_from_fun(tile_x, tile_y, res, noise).expect("Invalid field shape");
 
     let min = dbg!(field.min_by(f64::total_cmp));
     let max = dbg!(field.max_by(f64::total_cmp));
@@ -182,7 +182,7 @@ fn main() {
                 return Rgb([21, 148, 207]);
             };
 
-            let sunny = if let Some(_) = caster.cast(hit + sun_pos * 0.001, sun_pos) {
+            let sunny = if caster.cast(hit + sun_pos * 0.001, sun_pos).is_some() {
                 // It's in the shade
                 0.
             } else {
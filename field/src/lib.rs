@@ -1,16 +1,23 @@
 use std::{
+    cell::RefCell,
+    collections::HashMap,
     f64,
-    ops::{Add, Mul},
+    ops::{Add, Mul, Range},
+    sync::{Arc, OnceLock},
 };
 
-use grid::Grid;
+use grid::{Grid, Order};
 use nalgebra::{
     matrix, point, vector, Matrix2, Matrix2x3, Matrix3, Point2, Point3, Vector2, Vector3,
 };
+use thiserror::Error;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum TrigType {
+/// Orientation of a triangle of the tessellation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TrigType {
+    /// Triangle pointing down, above the diagonal of its square cell
     Upper,
+    /// Triangle pointing up, below the diagonal of its square cell
     Lower,
 }
 
@@ -28,6 +35,33 @@ pub struct Field<T> {
     res: f64,
     tile_x: f64,
     tile_y: f64,
+
+    /// What happens outside the tile
+    boundary: BoundaryMode,
+
+    /// Lazily calculated summary of the values, used by the raycaster
+    summary: OnceLock<Summary>,
+}
+
+/// How a field behaves outside its tile
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BoundaryMode {
+    /// The tile repeats in every direction
+    #[default]
+    Toroidal,
+    /// The values on the edges of the tile extend outward
+    ///
+    /// Gradients on the edges are one-sided, and outside the tile they are zero
+    /// orthogonally to the edges.
+    Clamp,
+}
+
+/// Summary of the values of a field
+#[derive(Debug, Clone, Copy)]
+struct Summary {
+    min_heigth: f64,
+    max_heigth: f64,
+    max_gradient: f64,
 }
 
 impl<T> Field<T> {
@@ -41,38 +75,191 @@ impl<T> Field<T> {
         trig_data_from_square_coords(pos)
     }
 
+    /// Find the triangle containing a point
+    ///
+    /// Returns:
+    /// - The indices `(col, row)` of the vertices of the containing triangle, not wrapped
+    /// - The barycentric coordinates of the point inside the triangle
+    /// - The type of the triangle
+    pub fn triangle_at(&self, pos: Point2<f64>) -> ([(isize, isize); 3], Point3<f64>, TrigType) {
+        self.trig_data(pos)
+    }
+
+    /// Find the vertex nearest to a point
+    ///
+    /// Returns the wrapped position `(col, row)` of the vertex in the grid, and its position
+    /// in world coordinates, not wrapped, so near the point.
+    /// The nearest vertex is always one of the vertices of the triangle containing the point.
+    /// Points exactly halfway between vertices snap to the one with the lower row, then to
+    /// the one with the lower column, in unwrapped coordinates.
+    pub fn nearest_vertex(&self, pos: Point2<f64>) -> ((usize, usize), Point2<f64>) {
+        let (idxs, _, _) = self.trig_data(pos);
+        let (col, row) = idxs
+            .into_iter()
+            .map(|idx| (idx, (self.vertex(idx).0 - pos.coords).norm_squared()))
+            .min_by(|((col_a, row_a), dist_a), ((col_b, row_b), dist_b)| {
+                dist_a
+                    .total_cmp(dist_b)
+                    .then(row_a.cmp(row_b))
+                    .then(col_a.cmp(col_b))
+            })
+            .unwrap()
+            .0;
+        let (wrapped_row, wrapped_col) = self.grid_index((col, row));
+        (
+            (wrapped_col, wrapped_row),
+            Point2::from(self.vertex((col, row)).0),
+        )
+    }
+
+    /// Iter through all the triangles of the tessellation of a single tile
+    pub fn triangles(&self) -> impl Iterator<Item = ([(isize, isize); 3], TrigType)> {
+        self.iter_trigs()
+    }
+
     /// Iter through all triangles
     fn iter_trigs(&self) -> impl Iterator<Item = ([(isize, isize); 3], TrigType)> {
         let rows = self.data.rows() as isize;
         let cols = self.data.cols() as isize;
-        (0..rows).flat_map(move |row| {
-            (0..cols).flat_map(move |col| {
-                [
-                    (
-                        [(col, row + 1), (col + 1, row), (col, row)],
-                        TrigType::Lower,
-                    ),
-                    (
-                        [(col, row + 1), (col + 1, row + 1), (col + 1, row)],
-                        TrigType::Upper,
-                    ),
-                ]
-            })
-        })
+        (0..rows).flat_map(move |row| (0..cols).flat_map(move |col| cell_trigs(col, row)))
+    }
+
+    /// Position in the grid `(row, col)` of a vertex, following the boundary mode
+    fn grid_index(&self, (col, row): (isize, isize)) -> (usize, usize) {
+        let rows = self.data.rows() as isize;
+        let cols = self.data.cols() as isize;
+        match self.boundary {
+            BoundaryMode::Toroidal => {
+                let d_col = (col + row.div_euclid(rows) * (rows / 2)).rem_euclid(cols) as usize;
+                let d_row = row.rem_euclid(rows) as usize;
+                (d_row, d_col)
+            }
+            BoundaryMode::Clamp => (
+                row.clamp(0, rows - 1) as usize,
+                col.clamp(0, cols - 1) as usize,
+            ),
+        }
+    }
+
+    /// Value at the vertex `(col, row)`, following the boundary mode outside the grid
+    ///
+    /// With [`BoundaryMode::Toroidal`] the indices wrap around the tile, with
+    /// [`BoundaryMode::Clamp`] they are clamped to the edges, so this never fails.
+    /// See [`Field::get_exact`] to access only the vertices inside the grid.
+    ///
+    /// # Panics
+    /// Panics if the field is empty
+    pub fn get(&self, col: isize, row: isize) -> &T {
+        &self.data[self.grid_index((col, row))]
+    }
+
+    /// Mutable value at the vertex `(col, row)`, see [`Field::get`]
+    ///
+    /// The cached summary is invalidated.
+    pub fn get_mut(&mut self, col: isize, row: isize) -> &mut T {
+        self.invalidate_cache();
+        let index = self.grid_index((col, row));
+        &mut self.data[index]
+    }
+
+    /// Value at the vertex `(col, row)`, or `None` if it is outside the grid
+    ///
+    /// Unlike [`Field::get`] the indices never wrap nor are clamped.
+    pub fn get_exact(&self, col: usize, row: usize) -> Option<&T> {
+        self.data.get(row, col)
+    }
+
+    /// Mutable value at the vertex `(col, row)`, see [`Field::get_exact`]
+    ///
+    /// The cached summary is invalidated.
+    pub fn get_exact_mut(&mut self, col: usize, row: usize) -> Option<&mut T> {
+        self.invalidate_cache();
+        self.data.get_mut(row, col)
     }
 
     fn vertex(&self, (col, row): (isize, isize)) -> (Vector2<f64>, &T) {
-        let d_col = (col
-            + row.div_euclid(self.data.rows() as isize) * (self.data.rows() as isize / 2))
-            .rem_euclid(self.data.cols() as isize) as usize;
-        let d_row = row.rem_euclid(self.data.rows() as isize) as usize;
         (
             self.from_square_coords * vector![col as f64, row as f64],
-            &self.data[(d_row, d_col)],
+            &self.data[self.grid_index((col, row))],
         )
     }
 
+    /// Iter through all the stored vertices, in row-major order
+    ///
+    /// Yields the world position of each vertex together with its value
+    pub fn iter_vertices(&self) -> impl Iterator<Item = (Point2<f64>, &T)> {
+        let cols = self.data.cols();
+        self.data.iter().enumerate().map(move |(i, value)| {
+            let pos = self.from_square_coords * point![(i % cols) as f64, (i / cols) as f64];
+            (pos, value)
+        })
+    }
+
+    /// Parallel version of [`Field::iter_vertices`]
+    #[cfg(feature = "rayon")]
+    pub fn par_iter_vertices(
+        &self,
+    ) -> impl rayon::iter::IndexedParallelIterator<Item = (Point2<f64>, &T)>
+    where
+        T: Sync,
+    {
+        use rayon::prelude::*;
+
+        let cols = self.data.cols();
+        self.data
+            .flatten()
+            .par_iter()
+            .enumerate()
+            .map(move |(i, value)| {
+                let pos = self.from_square_coords * point![(i % cols) as f64, (i / cols) as f64];
+                (pos, value)
+            })
+    }
+
+    /// Build a new field with the same shape, mapping each value by reference
+    ///
+    /// Unlike [`Field::map`], this does not consume `self`, and allocates a new grid instead
+    /// of reusing the old one.
+    pub fn map_ref<U>(&self, f: impl Fn(&T) -> U) -> Field<U> {
+        self.map_ref_with_coords(|_, value| f(value))
+    }
+
+    /// Build a new field with the same shape, mapping each value by reference together with
+    /// its position
+    ///
+    /// This is the borrowing version of [`Field::map_with_coords`].
+    pub fn map_ref_with_coords<U>(&self, f: impl Fn(Point2<f64>, &T) -> U) -> Field<U> {
+        let data = self
+            .iter_vertices()
+            .map(|(pos, value)| f(pos, value))
+            .collect();
+
+        Field {
+            data: Grid::from_vec(data, self.data.cols()),
+            from_square_coords: self.from_square_coords,
+            to_square_coords: self.to_square_coords,
+            lower_barycentric_coords_gradient: self.lower_barycentric_coords_gradient,
+            upper_barycentric_coords_gradient: self.upper_barycentric_coords_gradient,
+            res: self.res,
+            tile_x: self.tile_x,
+            tile_y: self.tile_y,
+            boundary: self.boundary,
+            summary: OnceLock::new(),
+        }
+    }
+
     pub fn map_with_coords<U>(self, f: impl Fn(Point2<f64>, T) -> U) -> Field<U> {
+        let from_square_coords = self.from_square_coords;
+        self.map_with_index(|(col, row), value| {
+            f(from_square_coords * point![col as f64, row as f64], value)
+        })
+    }
+
+    /// Map each value together with its grid index `(col, row)`
+    ///
+    /// The values are visited in row-major order, the same of [`Field::iter_vertices`],
+    /// and the indices match the ones used by [`Field::neighbors`].
+    pub fn map_with_index<U>(self, f: impl Fn((usize, usize), T) -> U) -> Field<U> {
         let Field {
             data,
             from_square_coords,
@@ -82,6 +269,8 @@ impl<T> Field<T> {
             res,
             tile_x,
             tile_y,
+            boundary,
+            summary: _,
         } = self;
 
         let (rows, cols) = data.size();
@@ -93,8 +282,7 @@ impl<T> Field<T> {
 
         for row in 0..rows {
             for col in 0..cols {
-                let pos = self.from_square_coords * point![col as f64, row as f64];
-                data.push(f(pos, old_data.next().unwrap()))
+                data.push(f((col, row), old_data.next().unwrap()))
             }
         }
 
@@ -109,6 +297,8 @@ impl<T> Field<T> {
             res,
             tile_x,
             tile_y,
+            boundary,
+            summary: OnceLock::new(),
         }
     }
 
@@ -122,6 +312,8 @@ impl<T> Field<T> {
             res,
             tile_x,
             tile_y,
+            boundary,
+            summary: _,
         } = self;
 
         let cols = data.cols();
@@ -136,16 +328,239 @@ impl<T> Field<T> {
             res,
             tile_x,
             tile_y,
+            boundary,
+            summary: OnceLock::new(),
+        }
+    }
+
+    /// Check if two fields share the same tiling, resolution, grid dimensions and boundary mode
+    fn same_shape<U>(&self, other: &Field<U>) -> bool {
+        self.tile_x == other.tile_x
+            && self.tile_y == other.tile_y
+            && self.res == other.res
+            && self.data.size() == other.data.size()
+            && self.boundary == other.boundary
+    }
+
+    /// Combine two fields elementwise
+    ///
+    /// # Panics
+    /// Panics if the two fields do not share the same tiling, resolution, grid dimensions
+    /// and boundary mode
+    pub fn zip_with<U, V>(self, other: Field<U>, f: impl Fn(T, U) -> V) -> Field<V> {
+        assert!(
+            self.same_shape(&other),
+            "Cannot combine fields with different shapes"
+        );
+
+        let Field {
+            data,
+            from_square_coords,
+            to_square_coords,
+            lower_barycentric_coords_gradient,
+            upper_barycentric_coords_gradient,
+            res,
+            tile_x,
+            tile_y,
+            boundary,
+            summary: _,
+        } = self;
+
+        let cols = data.cols();
+        let data = Grid::from_vec(
+            data.into_vec()
+                .into_iter()
+                .zip(other.data.into_vec())
+                .map(|(a, b)| f(a, b))
+                .collect(),
+            cols,
+        );
+
+        Field {
+            data,
+            from_square_coords,
+            to_square_coords,
+            lower_barycentric_coords_gradient,
+            upper_barycentric_coords_gradient,
+            res,
+            tile_x,
+            tile_y,
+            boundary,
+            summary: OnceLock::new(),
+        }
+    }
+
+    /// Build a field from already sampled values
+    ///
+    /// The grid must have the same size of the one [`Field::new`] would allocate for the
+    /// same parameters, with an even number of rows to ensure the square tiling.
+    /// As in [`Field::new`], the resolution must leave the tile some vertices.
+    /// The field is [`BoundaryMode::Toroidal`], see [`Field::from_grid_with_boundary`].
+    pub fn from_grid(
+        tile_x: f64,
+        tile_y: f64,
+        res: f64,
+        data: Grid<T>,
+    ) -> Result<Self, FromGridError> {
+        Self::from_grid_with_boundary(tile_x, tile_y, res, data, BoundaryMode::Toroidal)
+    }
+
+    /// Build a field from already sampled values, with the given behaviour outside the tile
+    ///
+    /// The grid is checked as in [`Field::from_grid`]. With [`BoundaryMode::Clamp`] the
+    /// gradients on the edges of the tile are one-sided, as only the vertices inside it are
+    /// known.
+    pub fn from_grid_with_boundary(
+        tile_x: f64,
+        tile_y: f64,
+        res: f64,
+        data: Grid<T>,
+        boundary: BoundaryMode,
+    ) -> Result<Self, FromGridError> {
+        let expected = grid_size(tile_x, tile_y, res);
+        if expected.0 == 0 || expected.1 == 0 {
+            return Err(FieldShapeError {
+                tile_x,
+                tile_y,
+                res,
+            }
+            .into());
+        }
+        if data.size() != expected {
+            return Err(ShapeMismatch {
+                expected,
+                found: data.size(),
+            }
+            .into());
+        }
+        Ok(Self::from_grid_unchecked(tile_x, tile_y, res, data).with_boundary(boundary))
+    }
+
+    /// Build a field around a grid, without checking its size
+    fn from_grid_unchecked(tile_x: f64, tile_y: f64, res: f64, data: Grid<T>) -> Self {
+        // the rest of the code relies on the memory layout being row-major
+        let data = if data.order() == Order::ColumnMajor {
+            let (rows, cols) = data.size();
+            let mut values: Vec<_> = data.into_vec().into_iter().map(Some).collect();
+            Grid::from_vec(
+                (0..rows * cols)
+                    .map(|i| values[(i % cols) * rows + i / cols].take().unwrap())
+                    .collect(),
+                cols,
+            )
+        } else {
+            data
+        };
+        let (rows, cols) = data.size();
+
+        let d_x = tile_x / cols as f64;
+        let d_y = tile_y * (2. / 3f64.sqrt()) / rows as f64;
+
+        let from_square_coords = matrix![d_x,0.;0.,d_y] * matrix![1.,0.5;0.,3f64.sqrt()/2.];
+        let to_square_coords = from_square_coords.try_inverse().unwrap();
+
+        /*
+            Here we precalculate the gradient of the baricentric coordinates.
+            It transforms as the transpose of the inverse of the basis transform.
+        */
+        //vector![v, u, 1. - v - u]
+        let lower_barycentric_coords_gradient = to_square_coords.transpose()
+            * matrix![
+                0.,1.,-1.;
+                1.,0.,-1.
+            ];
+        // vector![1. - u, v + u - 1., 1. - v]
+        let upper_barycentric_coords_gradient = to_square_coords.transpose()
+            * matrix![
+                -1.,1.,0.;
+                0.,1.,-1.
+            ];
+
+        Self {
+            data,
+
+            from_square_coords,
+            to_square_coords,
+
+            lower_barycentric_coords_gradient,
+            upper_barycentric_coords_gradient,
+
+            res,
+            tile_x,
+            tile_y,
+            boundary: BoundaryMode::Toroidal,
+            summary: OnceLock::new(),
+        }
+    }
+
+    /// Change how the field behaves outside its tile
+    ///
+    /// Fields are built [`BoundaryMode::Toroidal`], unless the mode is given to
+    /// [`Field::new_with_boundary`] or [`Field::from_grid_with_boundary`]
+    pub fn with_boundary(mut self, boundary: BoundaryMode) -> Self {
+        self.invalidate_cache();
+        self.boundary = boundary;
+        self
+    }
+
+    pub fn boundary(&self) -> BoundaryMode {
+        self.boundary
+    }
+
+    /// Grid of the values, indexed by `(row, col)` in square coordinates
+    pub fn as_grid(&self) -> &Grid<T> {
+        &self.data
+    }
+
+    /// Mutable access to the grid of the values
+    ///
+    /// Editing the grid directly bypasses any validation of the field shape: resizing it
+    /// or changing its memory order leaves the field in an inconsistent state.
+    /// The cached summary is invalidated.
+    pub fn as_grid_mut(&mut self) -> &mut Grid<T> {
+        self.invalidate_cache();
+        &mut self.data
+    }
+
+    /// Mutate each value in place
+    ///
+    /// Unlike [`Field::map`] this does not allocate, so it is the way to go for repeated
+    /// updates. The cached summary is invalidated.
+    pub fn apply(&mut self, f: impl FnMut(&mut T)) {
+        self.invalidate_cache();
+        self.data.iter_mut().for_each(f);
+    }
+
+    /// Mutate each value in place, knowing its position
+    ///
+    /// This is the in place version of [`Field::map_with_coords`].
+    /// The cached summary is invalidated.
+    pub fn apply_with_coords(&mut self, mut f: impl FnMut(Point2<f64>, &mut T)) {
+        self.invalidate_cache();
+        let cols = self.data.cols();
+        for (i, value) in self.data.iter_mut().enumerate() {
+            f(
+                self.from_square_coords * point![(i % cols) as f64, (i / cols) as f64],
+                value,
+            )
         }
     }
 
+    /// Unwrap the grid of the values
+    pub fn into_grid(self) -> Grid<T> {
+        self.data
+    }
+
+    /// Build a field sampling `fun` at each vertex
+    ///
+    /// Fails if the resolution is too coarse for the tile, see [`Field::new`]
     pub fn new_from_fun(
         tile_x: f64,
         tile_y: f64,
         res: f64,
         fun: impl Fn(Point2<f64>) -> T,
-    ) -> Self {
-        Field::new(tile_x, tile_y, res).map_with_coords(|coord, ()| fun(coord))
+    ) -> Result<Self, FieldShapeError> {
+        Ok(Field::new(tile_x, tile_y, res)?.map_with_coords(|coord, ()| fun(coord)))
     }
 
     pub fn min_by(&self, cmp: fn(&T, &T) -> std::cmp::Ordering) -> &T {
@@ -162,6 +577,54 @@ impl<T> Field<T> {
         }
     }
 
+    /// Indices `(col, row)` of the six vertices sharing a triangle edge with a vertex
+    ///
+    /// The neighbors are in counterclockwise order, starting from the next one in the same row:
+    /// `(col + 1, row)`, `(col, row + 1)`, `(col - 1, row + 1)`, `(col - 1, row)`,
+    /// `(col, row - 1)`, `(col + 1, row - 1)`. They are wrapped following the [`BoundaryMode`]:
+    /// on clamped edges the missing neighbors are replaced by the nearest vertex on the edge,
+    /// which can be the vertex itself.
+    pub fn neighbors(&self, col: usize, row: usize) -> [(usize, usize); 6] {
+        NEIGHBORS.map(|(d_col, d_row)| {
+            let (row, col) = self.grid_index((col as isize + d_col, row as isize + d_row));
+            (col, row)
+        })
+    }
+
+    /// Distance between two points, accounting for the tiling
+    ///
+    /// The field repeats with two periods: `cols` along the grid rows, and `rows` along the
+    /// grid columns, offset by `rows / 2` columns to undo the skew of the hexagonal tiling.
+    /// The difference between the points is reduced in square coordinates (through
+    /// `to_square_coords`) and the closest of the neighboring images is taken.
+    /// The [`BoundaryMode`] is not considered.
+    pub fn toroidal_distance(&self, a: Point2<f64>, b: Point2<f64>) -> f64 {
+        let rows = self.data.rows() as f64;
+        let cols = self.data.cols() as f64;
+        let period_x = vector![cols, 0.];
+        let period_y = vector![-((self.data.rows() / 2) as f64), rows];
+
+        let mut delta = self.to_square_coords * (b - a);
+        delta -= period_y * (delta.y / rows).round();
+        delta -= period_x * (delta.x / cols).round();
+
+        (-1..=1)
+            .flat_map(|i| (-1..=1).map(move |j| (i, j)))
+            .map(|(i, j)| {
+                let image = delta + period_x * i as f64 + period_y * j as f64;
+                (self.from_square_coords * image).norm()
+            })
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    /// Drop the cached summary of the values
+    ///
+    /// This must be called after editing the values of the field, so that
+    /// the raycaster does not use stale bounds
+    pub fn invalidate_cache(&mut self) {
+        self.summary.take();
+    }
+
     pub fn res(&self) -> f64 {
         self.res
     }
@@ -175,6 +638,74 @@ impl<T> Field<T> {
     }
 }
 
+/// The two triangles of the square cell with lower left vertex `(col, row)`
+fn cell_trigs(col: isize, row: isize) -> [([(isize, isize); 3], TrigType); 2] {
+    [
+        (
+            [(col, row + 1), (col + 1, row), (col, row)],
+            TrigType::Lower,
+        ),
+        (
+            [(col, row + 1), (col + 1, row + 1), (col + 1, row)],
+            TrigType::Upper,
+        ),
+    ]
+}
+
+/// Offsets `(col, row)` of the six vertices sharing a triangle edge with a vertex
+///
+/// They are in the counterclockwise order documented by [`Field::neighbors`].
+const NEIGHBORS: [(isize, isize); 6] = [(1, 0), (0, 1), (-1, 1), (-1, 0), (0, -1), (1, -1)];
+
+/// Size `(rows, cols)` of the grid of a field with the given parameters
+fn grid_size(tile_x: f64, tile_y: f64, res: f64) -> (usize, usize) {
+    let cols = (tile_x / res) as usize;
+    // rows is kept even to ensure square tiling
+    let rows = (tile_y * (1. / 3f64.sqrt()) / res) as usize * 2;
+    (rows, cols)
+}
+
+/// The resolution given to [`Field::new`] is too coarse for the tile
+#[derive(Debug, Clone, Copy, PartialEq, Error)]
+#[error("Tile of {tile_x}x{tile_y} at resolution {res} has no vertices, try a smaller resolution")]
+pub struct FieldShapeError {
+    pub tile_x: f64,
+    pub tile_y: f64,
+    pub res: f64,
+}
+
+/// The field has no vertices to sample
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("The field is empty")]
+pub struct EmptyField;
+
+/// The grid given to [`Field::from_grid`] has the wrong size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("Expected a grid of {expected:?} (rows, cols), found {found:?}")]
+pub struct ShapeMismatch {
+    pub expected: (usize, usize),
+    pub found: (usize, usize),
+}
+
+/// A field cannot be built by [`Field::from_grid`]
+#[derive(Debug, Clone, Copy, PartialEq, Error)]
+pub enum FromGridError {
+    #[error(transparent)]
+    Shape(#[from] FieldShapeError),
+    #[error(transparent)]
+    Mismatch(#[from] ShapeMismatch),
+}
+
+/// A field cannot be built by [`Field::from_gray_image`]
+#[cfg(feature = "image")]
+#[derive(Debug, Clone, Copy, PartialEq, Error)]
+pub enum FromImageError {
+    #[error("Cannot load a field from an empty image")]
+    EmptyImage,
+    #[error(transparent)]
+    Shape(#[from] FieldShapeError),
+}
+
 fn trig_data_from_square_coords(pos: Point2<f64>) -> ([(isize, isize); 3], Point3<f64>, TrigType) {
     let (col, u) = (pos.x.div_euclid(1.) as isize, pos.x.rem_euclid(1.));
     let (row, v) = (pos.y.div_euclid(1.) as isize, pos.y.rem_euclid(1.));
@@ -210,47 +741,42 @@ impl<T> Field<T>
 where
     T: Default,
 {
-    pub fn new(tile_x: f64, tile_y: f64, res: f64) -> Self {
-        let cols = (tile_x / res) as usize;
-        // rows is kept even to ensure square tiling
-        let rows = (tile_y * (1. / 3f64.sqrt()) / res) as usize * 2;
-
-        let d_x = tile_x / cols as f64;
-        let d_y = tile_y * (2. / 3f64.sqrt()) / rows as f64;
-
-        let from_square_coords = matrix![d_x,0.;0.,d_y] * matrix![1.,0.5;0.,3f64.sqrt()/2.];
-        let to_square_coords = from_square_coords.try_inverse().unwrap();
-
-        /*
-            Here we precalculate the gradient of the baricentric coordinates.
-            It transforms as the transpose of the inverse of the basis transform.
-        */
-        //vector![v, u, 1. - v - u]
-        let lower_barycentric_coords_gradient = to_square_coords.transpose()
-            * matrix![
-                0.,1.,-1.;
-                1.,0.,-1.
-            ];
-        // vector![1. - u, v + u - 1., 1. - v]
-        let upper_barycentric_coords_gradient = to_square_coords.transpose()
-            * matrix![
-                -1.,1.,0.;
-                0.,1.,-1.
-            ];
-
-        Self {
-            data: Grid::new(rows, cols),
-
-            from_square_coords,
-            to_square_coords,
-
-            lower_barycentric_coords_gradient,
-            upper_barycentric_coords_gradient,
+    /// Build a field filled with the default value
+    ///
+    /// Fails if the resolution is too coarse for the tile, so that the grid would be empty.
+    /// The field is [`BoundaryMode::Toroidal`], see [`Field::new_with_boundary`].
+    pub fn new(tile_x: f64, tile_y: f64, res: f64) -> Result<Self, FieldShapeError> {
+        Self::new_with_boundary(tile_x, tile_y, res, BoundaryMode::Toroidal)
+    }
 
-            res,
-            tile_x,
-            tile_y,
+    /// Build a field filled with the default value, with the given behaviour outside the tile
+    ///
+    /// Fails as [`Field::new`]. With [`BoundaryMode::Clamp`] the gradients on the edges of
+    /// the tile are one-sided, as only the vertices inside it are known.
+    pub fn new_with_boundary(
+        tile_x: f64,
+        tile_y: f64,
+        res: f64,
+        boundary: BoundaryMode,
+    ) -> Result<Self, FieldShapeError> {
+        let (rows, cols) = grid_size(tile_x, tile_y, res);
+        if rows == 0 || cols == 0 {
+            return Err(FieldShapeError {
+                tile_x,
+                tile_y,
+                res,
+            });
         }
+        Ok(Self::new_unchecked(tile_x, tile_y, res).with_boundary(boundary))
+    }
+
+    /// Build a field filled with the default value, without checking the resolution
+    ///
+    /// The number of columns and rows is truncated, so a resolution too coarse for the tile
+    /// produces an empty grid, and sampling the field will panic.
+    pub fn new_unchecked(tile_x: f64, tile_y: f64, res: f64) -> Self {
+        let (rows, cols) = grid_size(tile_x, tile_y, res);
+        Self::from_grid_unchecked(tile_x, tile_y, res, Grid::new(rows, cols))
     }
 }
 
@@ -258,8 +784,61 @@ impl<T> Field<T>
 where
     T: Clone,
 {
-    pub fn new_filled(tile_x: f64, tile_y: f64, res: f64, value: T) -> Self {
-        Field::new(tile_x, tile_y, res).map(|()| value.clone())
+    /// Build a field with the same value at each vertex
+    ///
+    /// Fails if the resolution is too coarse for the tile, see [`Field::new`]
+    pub fn new_filled(
+        tile_x: f64,
+        tile_y: f64,
+        res: f64,
+        value: T,
+    ) -> Result<Self, FieldShapeError> {
+        Ok(Field::new(tile_x, tile_y, res)?.map(|()| value.clone()))
+    }
+
+    /// Copy a rectangular region of the grid into a new field
+    ///
+    /// The ranges are of grid indices `(col, row)`, and must be inside the grid: ranges that
+    /// would wrap around the tile are rejected, crop the two sides separately instead.
+    /// The new field has the same resolution and boundary mode, and its tile is cut to the
+    /// region. Its first vertex is moved to the origin, so the point `p` of the subfield is
+    /// the point `p + o` of this field, where `o` is the position of the vertex
+    /// `(col_range.start, row_range.start)`.
+    /// `row_range` must have an even length, as the rows of any field, see [`Field::from_grid`].
+    ///
+    /// # Panics
+    /// Panics if a range is empty or goes past the end of the grid, or if `row_range` has an
+    /// odd length
+    pub fn subfield(&self, col_range: Range<usize>, row_range: Range<usize>) -> Field<T> {
+        let (rows, cols) = self.data.size();
+        assert!(
+            !col_range.is_empty() && col_range.end <= cols,
+            "Column range {col_range:?} is not inside the {cols} columns of the field"
+        );
+        assert!(
+            !row_range.is_empty() && row_range.end <= rows,
+            "Row range {row_range:?} is not inside the {rows} rows of the field"
+        );
+        assert!(
+            row_range.len().is_multiple_of(2),
+            "Row range {row_range:?} has an odd number of rows"
+        );
+        let sub_cols = col_range.len();
+        let data = Grid::from_vec(
+            row_range
+                .clone()
+                .flat_map(|row| col_range.clone().map(move |col| (row, col)))
+                .map(|pos| self.data[pos].clone())
+                .collect(),
+            sub_cols,
+        );
+        Self::from_grid_unchecked(
+            self.tile_x * sub_cols as f64 / cols as f64,
+            self.tile_y * row_range.len() as f64 / rows as f64,
+            self.res,
+            data,
+        )
+        .with_boundary(self.boundary)
     }
 }
 
@@ -268,7 +847,8 @@ where
     T: Add<T, Output = T> + Mul<f64, Output = T> + Clone,
 {
     /// Calculate gradient of a given triangle from the vertex indices and the precalculated coordinate gradients
-    /// This *might* use a cache
+    ///
+    /// This is not cached, see [`GradientCache`] to avoid recalculating it
     fn trig_gradient(&self, idxs: [(isize, isize); 3], trig_type: TrigType) -> Vector2<T> {
         let [g_x, g_y] = idxs
             .into_iter()
@@ -282,8 +862,8 @@ where
         vector![g_x, g_y]
     }
 
-    pub fn value(&self, pos: Point2<f64>) -> T {
-        let (idxs, coords, _) = self.trig_data(pos);
+    /// Interpolate the value inside a triangle from the barycentric coordinates
+    fn trig_value(&self, idxs: [(isize, isize); 3], coords: Point3<f64>) -> T {
         idxs.into_iter()
             .zip(coords.iter())
             .map(|(v, c)| self.vertex(v).1.clone() * *c)
@@ -291,39 +871,561 @@ where
             .unwrap()
     }
 
+    /// Interpolate the value at a point
+    ///
+    /// # Panics
+    /// Panics if the field is empty, see [`Field::try_value`]
+    pub fn value(&self, pos: Point2<f64>) -> T {
+        self.try_value(pos).expect("Cannot sample an empty field")
+    }
+
+    /// Interpolate the value at a point, failing if the field has no vertices
+    ///
+    /// A field built with [`Field::new`] or the other checked constructors is never empty,
+    /// so this can fail only for fields built with [`Field::new_unchecked`] or from empty grids.
+    pub fn try_value(&self, pos: Point2<f64>) -> Result<T, EmptyField> {
+        if self.data.is_empty() {
+            return Err(EmptyField);
+        }
+        let (idxs, coords, _) = self.trig_data(pos);
+        Ok(self.trig_value(idxs, coords))
+    }
+
     pub fn gradient(&self, pos: Point2<f64>) -> Vector2<T> {
         let (idxs, _, trig_type) = self.trig_data(pos);
         self.trig_gradient(idxs, trig_type)
     }
-}
 
-impl Field<f64> {
-    pub fn normal(&self, pos: Point2<f64>) -> Vector3<f64> {
-        let gradient = self.gradient(pos);
-        vector![-gradient.x, -gradient.y, 1.].normalize()
+    /// Wrap the field in a cache of the triangle gradients
+    pub fn gradient_cache(&self) -> GradientCache<'_, T> {
+        GradientCache {
+            field: self,
+            gradients: RefCell::new(HashMap::new()),
+        }
     }
 
-    /// Calculate the max gradient norm
-    pub fn max_gradient(&self) -> f64 {
-        self.iter_trigs()
-            .map(|(idxs, trig_type)| self.trig_gradient(idxs, trig_type).norm_squared())
-            .max_by(f64::total_cmp)
-            .unwrap()
-            .sqrt()
+    /// Sample the field at `n` evenly spaced points on a segment
+    ///
+    /// Both `from` and `to` are included. If `n` is 1 only `from` is sampled,
+    /// if `n` is 0 the result is empty.
+    pub fn sample_line(&self, from: Point2<f64>, to: Point2<f64>, n: usize) -> Vec<T> {
+        if n == 1 {
+            return vec![self.value(from)];
+        }
+        (0..n)
+            .map(|i| self.value(from + (to - from) * (i as f64 / (n - 1) as f64)))
+            .collect()
     }
 
-    /// Precalculate values for raycasting
-    pub fn raycaster(&self, RaycasterOptions { epsilon, max_dist }: RaycasterOptions) -> Raycaster {
-        Raycaster {
-            max_heigth: *self.max_by(f64::total_cmp),
-            min_heigth: *self.min_by(f64::total_cmp),
-            max_gradient: self.max_gradient(),
-            field: self,
-            epsilon,
-            max_dist,
-        }
+    /// Sample the field at a new resolution, keeping the same tiling
+    ///
+    /// Every vertex of the new field takes the interpolated value at its position.
+    /// No filtering is done: downsampling aliases the features smaller than the new resolution,
+    /// while upsampling cannot recover any detail and just subdivides the old triangles.
+    ///
+    /// Fails if the new resolution is too coarse for the tile, see [`Field::new`]
+    pub fn resample(&self, new_res: f64) -> Result<Field<T>, FieldShapeError> {
+        Ok(Field::new(self.tile_x, self.tile_y, new_res)?
+            .map_with_coords(|pos, ()| self.value(pos)))
     }
-}
+
+    /// Halve the number of vertices along each direction, doubling the resolution step
+    ///
+    /// This works directly on the grid, so it is much faster than [`Field::resample`].
+    /// Each new vertex keeps the position of one of the old ones, and its value is the
+    /// average of that vertex (weighted 1/2) and its six neighbors (weighted 1/12 each),
+    /// so that the field is not shifted.
+    ///
+    /// The halved grid is checked as in [`Field::from_grid`]: this fails if the number of
+    /// columns is odd, or if the number of rows is not a multiple of 4, as the halved grid
+    /// must still have an even number of rows, or if the doubled resolution is too coarse
+    /// for the tile.
+    pub fn downsample_2x(&self) -> Result<Field<T>, FromGridError> {
+        let (rows, cols) = self.data.size();
+        let mut data = Vec::with_capacity(rows.div_ceil(2) * cols.div_ceil(2));
+        for row in (0..rows as isize).step_by(2) {
+            for col in (0..cols as isize).step_by(2) {
+                let center = self.vertex((col, row)).1.clone() * 0.5;
+                data.push(NEIGHBORS.iter().fold(center, |acc, (d_col, d_row)| {
+                    acc + self.vertex((col + d_col, row + d_row)).1.clone() * (1. / 12.)
+                }));
+            }
+        }
+
+        Field::from_grid_with_boundary(
+            self.tile_x,
+            self.tile_y,
+            self.res * 2.,
+            Grid::from_vec(data, cols.div_ceil(2)),
+            self.boundary,
+        )
+    }
+}
+
+impl<T> Add for Field<T>
+where
+    T: Add<T, Output = T>,
+{
+    type Output = Field<T>;
+
+    /// Sum two fields elementwise
+    ///
+    /// # Panics
+    /// Panics if the two fields have different shapes, see [`Field::zip_with`]
+    fn add(self, rhs: Self) -> Self::Output {
+        self.zip_with(rhs, Add::add)
+    }
+}
+
+/// Cache of the gradients of the triangles of a field
+///
+/// The field is piecewise linear, so the gradient is the same in the whole triangle:
+/// the cached value is exact. Each triangle that was sampled takes an entry, so the cache grows
+/// up to the number of triangles sampled, counting separately the copies of a triangle in
+/// different tiles.
+/// The cache is kept outside the field so that the field stays [`Sync`].
+#[derive(Debug, Clone)]
+pub struct GradientCache<'f, T> {
+    field: &'f Field<T>,
+    gradients: RefCell<HashMap<TrigKey, Vector2<T>>>,
+}
+
+/// Unwrapped vertex indices and type of a triangle
+type TrigKey = ([(isize, isize); 3], TrigType);
+
+impl<'f, T> GradientCache<'f, T>
+where
+    T: Add<T, Output = T> + Mul<f64, Output = T> + Clone,
+{
+    /// Field the gradients are calculated on
+    pub fn field(&self) -> &'f Field<T> {
+        self.field
+    }
+
+    /// Gradient of the field, calculated once per triangle
+    pub fn gradient(&self, pos: Point2<f64>) -> Vector2<T> {
+        let (idxs, _, trig_type) = self.field.trig_data(pos);
+        self.gradients
+            .borrow_mut()
+            .entry((idxs, trig_type))
+            .or_insert_with(|| self.field.trig_gradient(idxs, trig_type))
+            .clone()
+    }
+
+    /// Number of triangles in the cache
+    pub fn len(&self) -> usize {
+        self.gradients.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.gradients.borrow().is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.gradients.get_mut().clear()
+    }
+}
+
+impl GradientCache<'_, f64> {
+    /// Normal of the field, calculated from the cached gradient
+    pub fn normal(&self, pos: Point2<f64>) -> Vector3<f64> {
+        let gradient = self.gradient(pos);
+        vector![-gradient.x, -gradient.y, 1.].normalize()
+    }
+}
+
+impl Field<f64> {
+    /// Multiply all the values by a constant factor
+    pub fn scale(self, factor: f64) -> Self {
+        self.map(|v| v * factor)
+    }
+
+    /// Load a heightmap from a grayscale image
+    ///
+    /// The image covers the whole tile, with the top row at `y = tile_y` as in the images
+    /// of the examples. Black maps to 0 and white to `height_scale`.
+    /// Each vertex is sampled bilinearly between the centers of the four nearest pixels,
+    /// wrapping around the edges of the image as the tile repeats, so the image and the field
+    /// do not need the same resolution.
+    ///
+    /// Fails if the image is empty, or if the resolution is too coarse for the tile, see
+    /// [`Field::new`]
+    #[cfg(feature = "image")]
+    pub fn from_gray_image(
+        img: &image::GrayImage,
+        tile_x: f64,
+        tile_y: f64,
+        res: f64,
+        height_scale: f64,
+    ) -> Result<Self, FromImageError> {
+        if img.width() == 0 || img.height() == 0 {
+            return Err(FromImageError::EmptyImage);
+        }
+        let (width, height) = (img.width() as i64, img.height() as i64);
+        let pixel = |x: i64, y: i64| {
+            img.get_pixel(x.rem_euclid(width) as u32, y.rem_euclid(height) as u32)[0] as f64
+                / u8::MAX as f64
+        };
+        Field::new_from_fun(tile_x, tile_y, res, |pos| {
+            // position in pixels, from the center of the top left one
+            let x = pos.x.rem_euclid(tile_x) / tile_x * width as f64 - 0.5;
+            let y = (1. - pos.y.rem_euclid(tile_y) / tile_y) * height as f64 - 0.5;
+            let (x_0, y_0) = (x.floor(), y.floor());
+            let (t_x, t_y) = (x - x_0, y - y_0);
+            let (x_0, y_0) = (x_0 as i64, y_0 as i64);
+            let top = pixel(x_0, y_0) * (1. - t_x) + pixel(x_0 + 1, y_0) * t_x;
+            let bottom = pixel(x_0, y_0 + 1) * (1. - t_x) + pixel(x_0 + 1, y_0 + 1) * t_x;
+            (top * (1. - t_y) + bottom * t_y) * height_scale
+        })
+        .map_err(Into::into)
+    }
+
+    /// Trace a path uphill, following the gradient
+    ///
+    /// The path starts at `start` and moves by `step` along the gradient, stopping when
+    /// the field is flat or after `max_steps` steps.
+    /// Being the field piecewise linear, near ridges and peaks the path can oscillate
+    /// back and forth around the top, within a step from it.
+    pub fn steepest_path(
+        &self,
+        start: Point2<f64>,
+        step: f64,
+        max_steps: usize,
+    ) -> Vec<Point2<f64>> {
+        const FLAT: f64 = 1e-12;
+
+        let mut path = vec![start];
+        let mut pos = start;
+        for _ in 0..max_steps {
+            let gradient = self.gradient(pos);
+            let norm = gradient.norm();
+            if norm < FLAT {
+                break;
+            }
+            pos += gradient * (step / norm);
+            path.push(pos);
+        }
+        path
+    }
+
+    /// Group the vertices below `threshold` into connected regions
+    ///
+    /// Each vertex is connected to the six vertices it shares a triangle edge with, wrapping
+    /// around the tile edges following the [`BoundaryMode`] of the field.
+    /// Regions are lists of grid positions `(col, row)`, sorted row by row, and are ordered
+    /// by their first vertex.
+    pub fn level_regions(&self, threshold: f64) -> Vec<Vec<(usize, usize)>> {
+        let (rows, cols) = self.data.size();
+        let below = |pos: (usize, usize)| self.data[pos] < threshold;
+        let mut visited = Grid::init(rows, cols, false);
+        let mut regions = vec![];
+        for row in 0..rows {
+            for col in 0..cols {
+                if visited[(row, col)] || !below((row, col)) {
+                    continue;
+                }
+                visited[(row, col)] = true;
+                let mut region = vec![];
+                let mut stack = vec![(row, col)];
+                while let Some((row, col)) = stack.pop() {
+                    region.push((col, row));
+                    for (d_col, d_row) in NEIGHBORS {
+                        let next = self.grid_index((col as isize + d_col, row as isize + d_row));
+                        if !visited[next] && below(next) {
+                            visited[next] = true;
+                            stack.push(next);
+                        }
+                    }
+                }
+                region.sort_unstable_by_key(|&(col, row)| (row, col));
+                regions.push(region);
+            }
+        }
+        regions
+    }
+
+    /// Blend with another field, following a mask
+    ///
+    /// Each value is `self * (1 - mask) + other * mask`, so where the mask is 0 the result
+    /// is `self` and where it is 1 it is `other`. The mask is clamped to `[0, 1]`.
+    ///
+    /// # Panics
+    /// Panics if the three fields do not share the same tiling, resolution, grid dimensions
+    /// and boundary mode, as for [`Field::zip_with`]
+    pub fn blend(&self, other: &Field<f64>, mask: &Field<f64>) -> Field<f64> {
+        assert!(
+            self.same_shape(other) && self.same_shape(mask),
+            "Cannot blend fields with different shapes"
+        );
+        let mut blended = self.clone();
+        blended.invalidate_cache();
+        for ((value, other), mask) in blended
+            .data
+            .iter_mut()
+            .zip(other.data.iter())
+            .zip(mask.data.iter())
+        {
+            let mask = mask.clamp(0., 1.);
+            *value = *value * (1. - mask) + other * mask;
+        }
+        blended
+    }
+
+    /// Elevation angles of the horizon seen from a point
+    ///
+    /// The horizon is sampled at `azimuth_samples` evenly spaced azimuths, the `i`-th at
+    /// `2π i / azimuth_samples` counterclockwise from the x axis. For each of them the terrain
+    /// is sampled marching outward by half the resolution, up to the diagonal of the tile, and
+    /// the result is the highest elevation angle, in radians, of the terrain over `pos`.
+    /// Negative angles mean that the terrain is everywhere below `pos` in that direction.
+    ///
+    /// The cost grows with the number of azimuths: peaks thinner than the angular spacing
+    /// at their distance can fall between two azimuths and be missed.
+    pub fn horizon(&self, pos: Point3<f64>, azimuth_samples: usize) -> Vec<f64> {
+        let step = self.res / 2.;
+        let max_dist = self.tile_x.hypot(self.tile_y);
+        (0..azimuth_samples)
+            .map(|i| {
+                let azimuth = f64::consts::TAU * i as f64 / azimuth_samples as f64;
+                let dir = vector![azimuth.cos(), azimuth.sin()];
+                (1..)
+                    .map(|n| n as f64 * step)
+                    .take_while(|&dist| dist <= max_dist)
+                    .map(|dist| (self.value(pos.xy() + dir * dist) - pos.z).atan2(dist))
+                    .fold(-f64::consts::FRAC_PI_2, f64::max)
+            })
+            .collect()
+    }
+
+    /// Route water downhill and measure how much passes through each vertex
+    ///
+    /// Every vertex receives a unit of water, that flows entirely to the neighbor with the
+    /// steepest descent, as in the D8 method with the six neighbors of the hex grid in place
+    /// of the eight of a square one. The result at each vertex counts its own unit plus all
+    /// the water flowing through it.
+    /// Vertices with no lower neighbor, as local minima and flat areas, are sinks: the water
+    /// reaching them pools there and does not flow further.
+    pub fn flow_accumulation(&self) -> Field<f64> {
+        let (rows, cols) = self.data.size();
+        let receiver = |(row, col): (usize, usize)| {
+            let height = self.data[(row, col)];
+            NEIGHBORS
+                .into_iter()
+                .filter_map(|(d_col, d_row)| {
+                    let next = self.grid_index((col as isize + d_col, row as isize + d_row));
+                    let drop = height - self.data[next];
+                    let dist =
+                        (self.from_square_coords * vector![d_col as f64, d_row as f64]).norm();
+                    (drop > 0.).then_some((drop / dist, next))
+                })
+                .max_by(|(a, _), (b, _)| a.total_cmp(b))
+                .map(|(_, next)| next)
+        };
+
+        // water flows only downhill: visiting from the top, all the water reaching a vertex
+        // has arrived before it flows on
+        let mut order: Vec<_> = (0..rows)
+            .flat_map(|row| (0..cols).map(move |col| (row, col)))
+            .collect();
+        order.sort_by(|&a, &b| self.data[b].total_cmp(&self.data[a]));
+
+        let mut flow = self.map_ref(|_| 1.);
+        for pos in order {
+            if let Some(next) = receiver(pos) {
+                let water = flow.data[pos];
+                flow.data[next] += water;
+            }
+        }
+        flow
+    }
+
+    /// Extract the contour line at a given level
+    ///
+    /// Every triangle crossed by the level contributes a single segment, found by linear
+    /// interpolation along its edges. The segments are in world coordinates, and are not
+    /// joined into polylines.
+    pub fn contour(&self, level: f64) -> Vec<(Point2<f64>, Point2<f64>)> {
+        self.iter_trigs()
+            .filter_map(|(idxs, _)| {
+                let vertices = idxs.map(|idx| {
+                    let (pos, &value) = self.vertex(idx);
+                    (pos, value)
+                });
+                let mut crossings = (0..3).filter_map(|i| {
+                    let (p_a, v_a) = vertices[i];
+                    let (p_b, v_b) = vertices[(i + 1) % 3];
+                    ((v_a >= level) != (v_b >= level)).then(|| {
+                        let t = (level - v_a) / (v_b - v_a);
+                        Point2::from(p_a + (p_b - p_a) * t)
+                    })
+                });
+                Some((crossings.next()?, crossings.next()?))
+            })
+            .collect()
+    }
+
+    /// Calculate values and normals for a batch of points
+    ///
+    /// The output is in the same order as the input.
+    /// Normals are calculated only once for each triangle, so this is faster
+    /// than calling [`Field::value`] and [`Field::normal`] when many points fall in the same triangle.
+    pub fn values_and_normals(&self, points: &[Point2<f64>]) -> Vec<(f64, Vector3<f64>)> {
+        let mut normals = HashMap::new();
+        points
+            .iter()
+            .map(|&pos| {
+                let (idxs, coords, trig_type) = self.trig_data(pos);
+                let normal = *normals.entry((idxs, trig_type)).or_insert_with(|| {
+                    let gradient = self.trig_gradient(idxs, trig_type);
+                    vector![-gradient.x, -gradient.y, 1.].normalize()
+                });
+                (self.trig_value(idxs, coords), normal)
+            })
+            .collect()
+    }
+}
+
+/// Values that can be used as heights of a terrain
+///
+/// Heights are stored in their own precision, and converted to `f64` when sampled.
+/// Storing `f32` halves the memory of the field, at the cost of precision.
+pub trait Height: Copy {
+    fn to_f64(self) -> f64;
+}
+
+impl Height for f64 {
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+impl Height for f32 {
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl<H: Height> Field<H> {
+    fn heights(&self) -> impl Iterator<Item = f64> + '_ {
+        self.data.iter().map(|h| h.to_f64())
+    }
+
+    fn height_gradient(&self, idxs: [(isize, isize); 3], trig_type: TrigType) -> Vector2<f64> {
+        idxs.into_iter()
+            .zip(self.trig_coord_grads(trig_type).column_iter())
+            .map(|(v, g)| g * self.vertex(v).1.to_f64())
+            .sum()
+    }
+
+    /// Interpolate the height at a point, in `f64`
+    ///
+    /// For a `Field<f64>` this is the same as [`Field::value`].
+    ///
+    /// # Panics
+    /// Panics if the field is empty
+    pub fn height(&self, pos: Point2<f64>) -> f64 {
+        assert!(!self.data.is_empty(), "Cannot sample an empty field");
+        let (idxs, coords, _) = self.trig_data(pos);
+        idxs.into_iter()
+            .zip(coords.iter())
+            .map(|(v, c)| self.vertex(v).1.to_f64() * c)
+            .sum()
+    }
+
+    pub fn normal(&self, pos: Point2<f64>) -> Vector3<f64> {
+        let (idxs, _, trig_type) = self.trig_data(pos);
+        let gradient = self.height_gradient(idxs, trig_type);
+        vector![-gradient.x, -gradient.y, 1.].normalize()
+    }
+
+    /// Calculate the max gradient norm
+    ///
+    /// The result is cached until [`Field::invalidate_cache`] is called
+    pub fn max_gradient(&self) -> f64 {
+        self.summary().max_gradient
+    }
+
+    /// Calculate the max gradient norm of the triangles around a point
+    ///
+    /// The neighborhood is made of the cells of the grid, each one split in two triangles,
+    /// that are at most `radius_cells` cells away from the one containing `pos` along
+    /// the grid axes, so it is a rhombus of `2 * radius_cells + 1` cells per side.
+    /// With `radius_cells` 0 only the containing cell is checked.
+    /// Unlike [`Field::max_gradient`] this is not cached.
+    pub fn local_max_gradient(&self, pos: Point2<f64>, radius_cells: usize) -> f64 {
+        let pos = self.to_square_coords * pos;
+        let (col, row) = (pos.x.floor() as isize, pos.y.floor() as isize);
+        let radius = radius_cells as isize;
+        (row - radius..=row + radius)
+            .flat_map(|row| (col - radius..=col + radius).flat_map(move |col| cell_trigs(col, row)))
+            .map(|(idxs, trig_type)| self.height_gradient(idxs, trig_type).norm_squared())
+            .fold(0., f64::max)
+            .sqrt()
+    }
+
+    /// Summary of the field values, calculated on first use
+    fn summary(&self) -> &Summary {
+        self.summary.get_or_init(|| Summary {
+            min_heigth: self.heights().min_by(f64::total_cmp).unwrap(),
+            max_heigth: self.heights().max_by(f64::total_cmp).unwrap(),
+            max_gradient: self
+                .iter_trigs()
+                .map(|(idxs, trig_type)| self.height_gradient(idxs, trig_type).norm_squared())
+                .max_by(f64::total_cmp)
+                .unwrap()
+                .sqrt(),
+        })
+    }
+
+    /// Precalculate values for raycasting
+    ///
+    /// The bounds of the field are cached, so repeated calls do not rescan the values.
+    ///
+    /// The raycasting itself is done in `f64`, but on a `f32` field the surface is only known
+    /// to about 7 significant digits: the hits can move by the rounding of the heights, so an
+    /// `epsilon` below it does not make them more precise.
+    pub fn raycaster(
+        &self,
+        RaycasterOptions { epsilon, max_dist }: RaycasterOptions,
+    ) -> Raycaster<'_, H> {
+        let &Summary {
+            min_heigth,
+            max_heigth,
+            max_gradient,
+        } = self.summary();
+        Raycaster {
+            max_heigth,
+            min_heigth,
+            max_gradient,
+            field: self,
+            epsilon,
+            max_dist,
+        }
+    }
+
+    /// Precalculate values for raycasting, moving the field inside the raycaster
+    ///
+    /// See [`Field::raycaster`]. The result can be stored and sent to other threads,
+    /// at the cost of an atomic reference count on each clone of the raycaster.
+    pub fn into_raycaster(self: Arc<Self>, options: RaycasterOptions) -> OwnedRaycaster<H> {
+        let Raycaster {
+            max_heigth,
+            min_heigth,
+            max_gradient,
+            epsilon,
+            max_dist,
+            field: _,
+        } = self.raycaster(options);
+        OwnedRaycaster {
+            field: self,
+            max_heigth,
+            min_heigth,
+            max_gradient,
+            epsilon,
+            max_dist,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Copy)]
 pub struct RaycasterOptions {
@@ -340,9 +1442,50 @@ impl Default for RaycasterOptions {
     }
 }
 
+impl RaycasterOptions {
+    /// Start building the options from the default ones
+    pub fn builder() -> RaycasterOptionsBuilder {
+        RaycasterOptionsBuilder::default()
+    }
+}
+
+/// Fluent builder for [`RaycasterOptions`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RaycasterOptionsBuilder(RaycasterOptions);
+
+impl RaycasterOptionsBuilder {
+    /// Set the minimum step of the raycaster
+    pub fn epsilon(mut self, epsilon: f64) -> Self {
+        self.0.epsilon = epsilon;
+        self
+    }
+    /// Set the distance after which rays are considered lost
+    pub fn max_dist(mut self, max_dist: f64) -> Self {
+        self.0.max_dist = max_dist;
+        self
+    }
+
+    pub fn build(self) -> RaycasterOptions {
+        self.0
+    }
+}
+
+/// Where a ray hit the field
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    /// Position of the hit
+    pub point: Point3<f64>,
+    /// Indices `(col, row)` of the vertices of the triangle that was hit, not wrapped
+    pub vertices: [(isize, isize); 3],
+    /// Barycentric coordinates of the hit inside the triangle
+    pub coords: Point3<f64>,
+    /// Type of the triangle that was hit
+    pub trig_type: TrigType,
+}
+
 #[derive(Debug, Clone, Copy)]
-pub struct Raycaster<'f> {
-    field: &'f Field<f64>,
+pub struct Raycaster<'f, H = f64> {
+    field: &'f Field<H>,
 
     max_heigth: f64,
     min_heigth: f64,
@@ -352,8 +1495,41 @@ pub struct Raycaster<'f> {
     max_dist: f64,
 }
 
-impl Raycaster<'_> {
+impl<'f, H: Height> Raycaster<'f, H> {
+    /// Field this raycaster is casting on
+    pub fn field(&self) -> &'f Field<H> {
+        self.field
+    }
+
     pub fn cast(&self, pos: Point3<f64>, dir: Vector3<f64>) -> Option<Point3<f64>> {
+        self.cast_detailed(pos, dir).map(|hit| hit.point)
+    }
+
+    /// Cast a ray, giving up after `max_dist` instead of the distance set in the options
+    ///
+    /// The override takes precedence over [`RaycasterOptions::max_dist`] for this call only,
+    /// whether it is shorter or longer.
+    pub fn cast_within(
+        &self,
+        pos: Point3<f64>,
+        dir: Vector3<f64>,
+        max_dist: f64,
+    ) -> Option<Point3<f64>> {
+        self.cast_detailed_within(pos, dir, max_dist)
+            .map(|hit| hit.point)
+    }
+
+    /// Cast a ray, reporting also the triangle that was hit
+    pub fn cast_detailed(&self, pos: Point3<f64>, dir: Vector3<f64>) -> Option<RayHit> {
+        self.cast_detailed_within(pos, dir, self.max_dist)
+    }
+
+    fn cast_detailed_within(
+        &self,
+        pos: Point3<f64>,
+        dir: Vector3<f64>,
+        max_dist: f64,
+    ) -> Option<RayHit> {
         // calculating where the ray will exit the surface semiplanes
         let i_min = (self.min_heigth - pos.z) / dir.z;
         let i_max = (self.max_heigth - pos.z) / dir.z;
@@ -363,9 +1539,12 @@ impl Raycaster<'_> {
             return None;
         }
 
+        // If the ray starts inside the height band one of the two is negative
+        let (i_min, i_max) = (i_min.max(0.), i_max.max(0.));
+
         // Calculating the entering and exiting cells
-        let mut advanced = f64::min(self.epsilon, f64::min(i_min, i_max));
-        let end = f64::min(f64::max(i_min, i_max), self.max_dist);
+        let mut advanced = f64::max(self.epsilon, f64::min(i_min, i_max));
+        let end = f64::min(f64::max(i_min, i_max), max_dist);
 
         // Opening of the max gradient cone
         let cone_opening = 1. / (dir.z.abs() + self.max_gradient * dir.xy().norm());
@@ -375,10 +1554,10 @@ impl Raycaster<'_> {
 
             // checks if we hit the triangle under us. If not, return the height of the terrain under us
 
-            let (trig, coords, _) = self.field.trig_data(current_pos.xy());
+            let (trig, coords, trig_type) = self.field.trig_data(current_pos.xy());
             let vertices = trig.map(|idx| {
                 let (o, h) = self.field.vertex(idx);
-                point![o.x, o.y, *h]
+                point![o.x, o.y, h.to_f64()]
             });
 
             // Find the plane/line intersection
@@ -395,7 +1574,12 @@ impl Raycaster<'_> {
 
                 // check if the intersection is inside the triangle AND in the positive semi-ray
                 if t > 0. && u >= 0. && v >= 0. && u + v <= 1. {
-                    return Some(pos + t * dir);
+                    return Some(RayHit {
+                        point: pos + t * dir,
+                        vertices: trig,
+                        coords: point![1. - u - v, u, v],
+                        trig_type,
+                    });
                 }
             } else {
                 // no intersection, ray is coplanar
@@ -426,7 +1610,7 @@ impl Raycaster<'_> {
                     let u = intersection.y;
 
                     // Is the intersection in the side of the triangle?
-                    if t > 0. && u >= 0. && u <= 1. {
+                    if t > 0. && (0. ..=1.).contains(&u) {
                         delta = delta.max(t)
                     }
                 } else {
@@ -440,3 +1624,685 @@ impl Raycaster<'_> {
         None
     }
 }
+
+/// A [`Raycaster`] sharing the ownership of its field
+///
+/// Built with [`Field::into_raycaster`]. It casts the same rays of the borrowed raycaster,
+/// but has no lifetime, so it can be stored or moved into other threads.
+#[derive(Debug, Clone)]
+pub struct OwnedRaycaster<H = f64> {
+    field: Arc<Field<H>>,
+
+    max_heigth: f64,
+    min_heigth: f64,
+    max_gradient: f64,
+
+    epsilon: f64,
+    max_dist: f64,
+}
+
+impl<H: Height> OwnedRaycaster<H> {
+    /// Borrow as a [`Raycaster`]
+    pub fn as_raycaster(&self) -> Raycaster<'_, H> {
+        Raycaster {
+            field: &self.field,
+            max_heigth: self.max_heigth,
+            min_heigth: self.min_heigth,
+            max_gradient: self.max_gradient,
+            epsilon: self.epsilon,
+            max_dist: self.max_dist,
+        }
+    }
+
+    /// Field this raycaster is casting on
+    pub fn field(&self) -> &Arc<Field<H>> {
+        &self.field
+    }
+
+    pub fn cast(&self, pos: Point3<f64>, dir: Vector3<f64>) -> Option<Point3<f64>> {
+        self.as_raycaster().cast(pos, dir)
+    }
+
+    /// See [`Raycaster::cast_within`]
+    pub fn cast_within(
+        &self,
+        pos: Point3<f64>,
+        dir: Vector3<f64>,
+        max_dist: f64,
+    ) -> Option<Point3<f64>> {
+        self.as_raycaster().cast_within(pos, dir, max_dist)
+    }
+
+    /// See [`Raycaster::cast_detailed`]
+    pub fn cast_detailed(&self, pos: Point3<f64>, dir: Vector3<f64>) -> Option<RayHit> {
+        self.as_raycaster().cast_detailed(pos, dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use grid::Grid;
+    use nalgebra::{point, vector, Vector2};
+
+    use crate::{BoundaryMode, EmptyField, Field, FromGridError, RaycasterOptions};
+
+    #[test]
+    fn add_constant_fields() {
+        let a = Field::new_filled(4., 4., 0.5, 1.5).unwrap();
+        let b = Field::new_filled(4., 4., 0.5, 2.).unwrap();
+        let sum = (a + b.scale(2.)).map(|v| v - 5.5);
+        assert_eq!(*sum.min_by(f64::total_cmp), 0.);
+        assert_eq!(*sum.max_by(f64::total_cmp), 0.);
+    }
+
+    #[test]
+    fn raycaster_caches_summary() {
+        let mut field = Field::new_from_fun(4., 4., 0.5, |p| p.x).unwrap();
+        assert!(field.summary.get().is_none());
+
+        let first = field.raycaster(Default::default()).max_heigth;
+        let cached = field.summary.get().unwrap() as *const _;
+        let second = field.raycaster(Default::default()).max_heigth;
+        // the summary was not recalculated
+        assert_eq!(field.summary.get().unwrap() as *const _, cached);
+        assert_eq!(first, second);
+
+        field.invalidate_cache();
+        assert!(field.summary.get().is_none());
+    }
+
+    #[test]
+    fn iter_vertices_counts() {
+        let field = Field::new_filled(4., 3., 0.25, 0.).unwrap();
+        let (rows, cols) = field.data.size();
+        assert_eq!(field.iter_vertices().count(), rows * cols);
+    }
+
+    #[test]
+    fn grid_round_trip() {
+        let field = Field::new_filled(4., 3., 0.25, 7.).unwrap();
+        let grid = field.clone().into_grid();
+        let rebuilt = Field::from_grid(4., 3., 0.25, grid).unwrap();
+        assert_eq!(rebuilt.data.size(), field.data.size());
+        assert_eq!(rebuilt.data.flatten(), field.data.flatten());
+        assert_eq!(rebuilt.from_square_coords, field.from_square_coords);
+    }
+
+    #[test]
+    fn grid_mut_invalidates_cache() {
+        let mut field = Field::new_filled(4., 3., 0.25, 0.).unwrap();
+        assert_eq!(field.raycaster(Default::default()).max_heigth, 0.);
+        field.as_grid_mut()[(0, 0)] = 1.;
+        assert_eq!(field.raycaster(Default::default()).max_heigth, 1.);
+    }
+
+    #[test]
+    fn grid_wrong_shape() {
+        let grid = Field::new_filled(4., 3., 0.25, 7.).unwrap().into_grid();
+        assert!(matches!(
+            Field::from_grid(4., 3., 0.5, grid),
+            Err(FromGridError::Mismatch(_))
+        ));
+        // the grid matches, but the resolution is too coarse for the tile
+        assert!(matches!(
+            Field::<f64>::from_grid(1., 1., 10., Grid::new(0, 0)),
+            Err(FromGridError::Shape(_))
+        ));
+    }
+
+    #[test]
+    fn cast_from_inside_band() {
+        let mut field = Field::new_filled(4., 3., 0.25, 0.).unwrap();
+        // a single spike far from the camera lifts the max height above it
+        field.as_grid_mut()[(0, 0)] = 10.;
+
+        let raycaster = field.raycaster(Default::default());
+
+        let hit = raycaster
+            .cast(point![2.1, 1.3, 5.], vector![0., 0., -1.])
+            .unwrap();
+        assert!((hit - point![2.1, 1.3, 0.]).norm() < 1e-9);
+
+        // a horizontal ray exits the band behind the camera at an infinite distance
+        let hit = raycaster
+            .cast(point![2.1, 0.05, 5.], vector![-1., 0., 0.])
+            .unwrap();
+        assert!((hit - point![0.1, 0.05, 5.]).norm() < 1e-9);
+        assert!((field.value(hit.xy()) - 5.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn resample_constant() {
+        let field = Field::new_filled(4., 3., 0.25, 3.).unwrap();
+        for res in [0.1, 0.3, 0.7] {
+            let resampled = field.resample(res).unwrap();
+            assert_eq!(resampled.res(), res);
+            for (_, v) in resampled.iter_vertices() {
+                assert!((v - 3.).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn barycentric_coords_sum() {
+        let field = Field::new_filled(4., 3., 0.25, 0.).unwrap();
+        for pos in [point![0.1, 0.1], point![1.33, 2.7], point![-5.2, 8.1]] {
+            let (_, coords, _) = field.triangle_at(pos);
+            assert!((coords.x + coords.y + coords.z - 1.).abs() < 1e-12);
+        }
+        let (rows, cols) = field.as_grid().size();
+        assert_eq!(field.triangles().count(), 2 * rows * cols);
+    }
+
+    #[test]
+    fn batch_values_and_normals() {
+        let field = Field::new_from_fun(4., 3., 0.25, |p| (p.x * 2.).sin() * p.y.cos()).unwrap();
+        let points: Vec<_> = (0..200)
+            .map(|i| point![i as f64 * 0.031, (i % 17) as f64 * 0.2])
+            .collect();
+        for (pos, (value, normal)) in points.iter().zip(field.values_and_normals(&points)) {
+            assert_eq!(value, field.value(*pos));
+            assert_eq!(normal, field.normal(*pos));
+        }
+    }
+
+    #[test]
+    fn sample_line_constant() {
+        let field = Field::new_filled(4., 3., 0.25, 2.).unwrap();
+        let samples = field.sample_line(point![-1., 0.3], point![5., 2.], 50);
+        assert_eq!(samples.len(), 50);
+        assert!(samples.iter().all(|v| (v - 2.).abs() < 1e-12));
+        assert_eq!(
+            field.sample_line(point![0., 0.], point![1., 1.], 1).len(),
+            1
+        );
+        assert!(field
+            .sample_line(point![0., 0.], point![1., 1.], 0)
+            .is_empty());
+    }
+
+    #[test]
+    fn hit_triangle_contains_hit() {
+        let field = Field::new_from_fun(4., 3., 0.25, |p| (p.x * 2.).sin() * p.y.cos()).unwrap();
+        let caster = field.raycaster(Default::default());
+        for i in 0..20 {
+            let dir = vector![(i as f64 * 0.7).cos(), (i as f64 * 0.7).sin(), -0.4];
+            let hit = caster
+                .cast_detailed(point![1., 1., 2.], dir.normalize())
+                .unwrap();
+            assert!(hit.coords.iter().all(|c| (-1e-9..=1. + 1e-9).contains(c)));
+            // the hit is the barycentric combination of the vertices
+            let rebuilt: Vector2<f64> = hit
+                .vertices
+                .iter()
+                .zip(hit.coords.iter())
+                .map(|(v, c)| field.vertex(*v).0 * *c)
+                .sum();
+            assert!((rebuilt - hit.point.xy().coords).norm() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn steepest_path_reaches_peak() {
+        let field =
+            Field::new_from_fun(4., 3., 0.1, |p| -((p.x - 2.).powi(2) + (p.y - 1.5).powi(2)))
+                .unwrap();
+        let path = field.steepest_path(point![1., 1.], 0.05, 200);
+        assert_eq!(path[0], point![1., 1.]);
+        assert!((path.last().unwrap() - point![2., 1.5]).norm() < 0.2);
+    }
+
+    #[test]
+    fn sample_outside_tile() {
+        let toroidal = Field::new_from_fun(4., 3., 0.25, |p| p.x).unwrap();
+        let clamped = toroidal.clone().with_boundary(BoundaryMode::Clamp);
+        // the mode can also be given when building the field
+        let rebuilt = Field::from_grid_with_boundary(
+            4.,
+            3.,
+            0.25,
+            toroidal.clone().into_grid(),
+            BoundaryMode::Clamp,
+        )
+        .unwrap();
+        assert_eq!(rebuilt.boundary(), BoundaryMode::Clamp);
+        assert_eq!(
+            rebuilt.value(point![4.1, 0.01]),
+            clamped.value(point![4.1, 0.01])
+        );
+        let zeros = Field::<f64>::new_with_boundary(4., 3., 0.25, BoundaryMode::Clamp).unwrap();
+        assert_eq!(zeros.boundary(), BoundaryMode::Clamp);
+        assert_eq!(
+            Field::<f64>::new(4., 3., 0.25).unwrap().boundary(),
+            BoundaryMode::Toroidal
+        );
+
+        // past the right edge the toroidal field restarts from the left one
+        assert!((toroidal.value(point![4.1, 0.01]) - 0.1).abs() < 1e-9);
+        let v = clamped.value(point![4.1, 0.01]);
+        assert!((3.75..3.8).contains(&v));
+
+        // before the left edge the toroidal field wraps to the right one
+        assert!(
+            (toroidal.value(point![-0.1, 0.01]) - toroidal.value(point![3.9, 0.01])).abs() < 1e-9
+        );
+        let v = clamped.value(point![-0.1, 0.01]);
+        assert!((0. ..0.05).contains(&v));
+    }
+
+    #[test]
+    fn map_ref_matches_map() {
+        let field = Field::new_from_fun(4., 3., 0.25, |p| p.x * p.y).unwrap();
+        let by_ref = field.map_ref(|v| v * 2.);
+        let by_value = field.clone().map(|v| v * 2.);
+        assert_eq!(by_ref.as_grid().flatten(), by_value.as_grid().flatten());
+
+        let by_ref = field.map_ref_with_coords(|p, v| p.x + v);
+        let by_value = field.clone().map_with_coords(|p, v| p.x + v);
+        assert_eq!(by_ref.as_grid().flatten(), by_value.as_grid().flatten());
+    }
+
+    #[test]
+    fn coarse_resolution() {
+        let err = Field::<f64>::new(1.0, 1.0, 10.0).unwrap_err();
+        assert_eq!(err.res, 10.0);
+        assert!(Field::new_filled(1.0, 1.0, 10.0, 0.).is_err());
+        // the unchecked constructor keeps the empty grid
+        let empty = Field::<f64>::new_unchecked(1.0, 1.0, 10.0);
+        assert!(empty.as_grid().is_empty());
+        assert_eq!(empty.try_value(point![0.5, 0.5]), Err(EmptyField));
+    }
+
+    #[test]
+    fn two_basins() {
+        let field =
+            Field::new_from_fun(4., 3., 0.25, |p| (p.x * std::f64::consts::PI).cos()).unwrap();
+        let regions = field.level_regions(-0.5);
+        assert_eq!(regions.len(), 2);
+        for region in &regions {
+            for &(col, row) in region {
+                assert!(*field.get_exact(col, row).unwrap() < -0.5);
+            }
+        }
+        // the two basins are around x = 1 and x = 3
+        for (region, x) in regions.iter().zip([1., 3.]) {
+            let (col, row) = region[0];
+            let (pos, _) = field
+                .iter_vertices()
+                .nth(row * field.as_grid().cols() + col)
+                .unwrap();
+            assert!((pos.x.rem_euclid(4.) - x).abs() < 0.5);
+        }
+        // nothing is below the minimum
+        assert!(field.level_regions(-1.5).is_empty());
+        // everything is connected below the maximum
+        assert_eq!(field.level_regions(1.5).len(), 1);
+    }
+
+    #[test]
+    fn contour_of_ramp() {
+        let field = Field::new_from_fun(4., 3., 0.25, |p| p.y).unwrap();
+        // leave out the segments near the seam, where the ramp jumps back to zero
+        let segments: Vec<_> = field
+            .contour(1.1)
+            .into_iter()
+            .filter(|(a, b)| a.y < 2.5 && b.y < 2.5)
+            .collect();
+        assert!(!segments.is_empty());
+        for (a, b) in &segments {
+            assert!((a.y - 1.1).abs() < 1e-9 && (b.y - 1.1).abs() < 1e-9);
+        }
+        // the line crosses the whole tile
+        let length: f64 = segments.iter().map(|(a, b)| (b - a).norm()).sum();
+        assert!((length - 4.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn distance_across_edges() {
+        let field = Field::new_filled(4., 3., 0.25, 0.).unwrap();
+        let (a, b) = (point![0.1, 1.5], point![3.9, 1.5]);
+        assert!((field.toroidal_distance(a, b) - 0.2).abs() < 1e-9);
+        assert!(field.toroidal_distance(a, b) < (b - a).norm());
+        let (a, b) = (point![2., 0.1], point![2., 2.9]);
+        assert!((field.toroidal_distance(a, b) - 0.2).abs() < 1e-9);
+        // points in the middle are unaffected
+        let (a, b) = (point![1., 1.], point![2., 2.]);
+        assert!((field.toroidal_distance(a, b) - (b - a).norm()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn downsample_constant() {
+        let field = Field::new_filled(4., 3., 0.25, 2.5).unwrap();
+        let small = field.downsample_2x().unwrap();
+        assert_eq!(small.as_grid().size(), (6, 8));
+        assert_eq!(small.res(), 0.5);
+        for (_, v) in small.iter_vertices() {
+            assert!((v - 2.5).abs() < 1e-12);
+        }
+
+        // linear fields are preserved away from the seams, so the vertices did not move
+        let ramp = Field::new_from_fun(4., 3., 0.25, |p| p.x + 2. * p.y).unwrap();
+        let small = ramp.downsample_2x().unwrap();
+        let pos = point![2.1, 1.3];
+        assert!((small.value(pos) - ramp.value(pos)).abs() < 1e-9);
+
+        // halving 6 rows would leave an odd number of them
+        let field = Field::new_filled(4., 1.4, 0.25, 2.5).unwrap();
+        assert_eq!(field.as_grid().rows(), 6);
+        assert!(matches!(
+            field.downsample_2x(),
+            Err(FromGridError::Mismatch(_))
+        ));
+        // a single row of triangles cannot be halved at all
+        let field = Field::new_filled(4., 0.5, 0.25, 2.5).unwrap();
+        assert_eq!(field.as_grid().rows(), 2);
+        assert!(matches!(
+            field.downsample_2x(),
+            Err(FromGridError::Shape(_))
+        ));
+    }
+
+    #[test]
+    fn cast_within_override() {
+        let field = Field::new_from_fun(4., 3., 0.25, |p| p.x.sin()).unwrap();
+        let options = RaycasterOptions::builder().max_dist(100.).build();
+        assert_eq!(options.epsilon, RaycasterOptions::default().epsilon);
+        let caster = field.raycaster(options);
+
+        let (pos, dir) = (point![1., 1., 5.], vector![0., 0., -1.]);
+        assert!(caster.cast(pos, dir).is_some());
+        assert!(caster.cast_within(pos, dir, 2.).is_none());
+        assert_eq!(caster.cast_within(pos, dir, 100.), caster.cast(pos, dir));
+    }
+
+    #[test]
+    fn neighbors_are_symmetric() {
+        let field = Field::new_filled(4., 3., 0.5, 0.).unwrap();
+        let (rows, cols) = field.as_grid().size();
+        for row in 0..rows {
+            for col in 0..cols {
+                let neighbors = field.neighbors(col, row);
+                for (n_col, n_row) in neighbors {
+                    assert!(field.neighbors(n_col, n_row).contains(&(col, row)));
+                }
+                // the six neighbors are distinct
+                for i in 0..6 {
+                    assert!(!neighbors[i + 1..].contains(&neighbors[i]));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn apply_matches_map() {
+        let field = Field::new_from_fun(4., 3., 0.25, |p| p.x * p.y).unwrap();
+
+        let mut applied = field.clone();
+        applied.apply(|x| *x += 1.0);
+        let mapped = field.clone().map(|x| x + 1.0);
+        assert_eq!(applied.as_grid().flatten(), mapped.as_grid().flatten());
+
+        let mut applied = field.clone();
+        applied.apply_with_coords(|p, x| *x -= p.x);
+        let mapped = field.clone().map_with_coords(|p, x| x - p.x);
+        assert_eq!(applied.as_grid().flatten(), mapped.as_grid().flatten());
+    }
+
+    #[test]
+    fn snap_to_vertex() {
+        let field = Field::new_from_fun(4., 3., 0.25, |p| p.x + p.y).unwrap();
+        for (pos, value) in field.iter_vertices().step_by(7) {
+            for offset in [vector![1e-3, 0.], vector![-1e-3, 1e-3], vector![0., -1e-3]] {
+                let ((col, row), snapped) = field.nearest_vertex(pos + offset);
+                assert!((snapped - pos).norm() < 1e-9);
+                assert_eq!(field.get_exact(col, row), Some(value));
+            }
+        }
+        // wrapping around the tile
+        let (idx, snapped) = field.nearest_vertex(point![4. - 1e-3, 1e-3]);
+        assert_eq!(idx, (0, 0));
+        assert!((snapped - point![4., 0.]).norm() < 1e-9);
+        // the position is the one taken by the other methods
+        let ((col, row), snapped) = field.nearest_vertex(point![2., 1.3]);
+        assert_eq!(field.get_exact(col, row), Some(&(snapped.x + snapped.y)));
+        assert!(field
+            .neighbors(col, row)
+            .contains(&field.nearest_vertex(snapped + vector![0.25, 0.]).0));
+    }
+
+    #[test]
+    fn f32_heights() {
+        let fun = |p: nalgebra::Point2<f64>| (p.x * 2.).sin() * p.y.cos() * 10.;
+        let wide = Field::new_from_fun(4., 3., 0.25, fun).unwrap();
+        let narrow = Field::new_from_fun(4., 3., 0.25, |p| fun(p) as f32).unwrap();
+        for i in 0..100 {
+            let pos = point![i as f64 * 0.037, i as f64 * 0.021];
+            assert!((narrow.height(pos) - wide.value(pos)).abs() < 1e-5);
+            assert!((narrow.normal(pos) - wide.normal(pos)).norm() < 1e-5);
+        }
+        let options = RaycasterOptions::default();
+        let (pos, dir) = (point![0.3, 0.7, 20.], vector![0.2, 0.1, -1.]);
+        let hit = narrow.raycaster(options).cast(pos, dir).unwrap();
+        let expected = wide.raycaster(options).cast(pos, dir).unwrap();
+        assert!((hit - expected).norm() < 1e-4);
+    }
+
+    #[test]
+    fn owned_raycaster() {
+        let field =
+            Field::new_from_fun(4., 4., 0.25, |p| (p.x * 3.).sin() + (p.y * 2.).cos()).unwrap();
+        let options = RaycasterOptions::default();
+        let owned = Arc::new(field.clone()).into_raycaster(options);
+        let borrowed = field.raycaster(options);
+        let owned = std::thread::spawn(move || owned).join().unwrap();
+        for i in 0..20 {
+            let pos = point![i as f64 * 0.3, i as f64 * 0.1, 5.];
+            let dir = vector![1., (i as f64).cos(), -0.5];
+            assert_eq!(
+                owned.cast_detailed(pos, dir),
+                borrowed.cast_detailed(pos, dir)
+            );
+        }
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn constant_gray_image() {
+        let img = image::GrayImage::from_pixel(7, 5, image::Luma([51]));
+        let field = Field::from_gray_image(&img, 4., 3., 0.25, 10.).unwrap();
+        assert!((*field.min_by(f64::total_cmp) - 2.).abs() < 1e-12);
+        assert!((*field.max_by(f64::total_cmp) - 2.).abs() < 1e-12);
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn gray_image_orientation() {
+        // white on the top half, black on the bottom half
+        let img =
+            image::GrayImage::from_fn(8, 8, |_, y| image::Luma([if y < 4 { 255 } else { 0 }]));
+        let field = Field::from_gray_image(&img, 4., 4., 0.125, 1.).unwrap();
+        assert!((field.value(point![2., 3.]) - 1.).abs() < 1e-12);
+        assert!(field.value(point![2., 1.]).abs() < 1e-12);
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn empty_gray_image() {
+        let img = image::GrayImage::new(0, 5);
+        assert_eq!(
+            Field::from_gray_image(&img, 4., 3., 0.25, 1.).unwrap_err(),
+            crate::FromImageError::EmptyImage
+        );
+    }
+
+    #[test]
+    fn map_with_index() {
+        let field = Field::new_filled(4., 3., 0.5, ()).unwrap();
+        let cols = field.as_grid().cols();
+        let field = field.map_with_index(|(col, row), ()| row * cols + col);
+        let flat: Vec<_> = field.iter_vertices().map(|(_, &i)| i).collect();
+        assert_eq!(flat, (0..flat.len()).collect::<Vec<_>>());
+        // grids are indexed by (row, col)
+        assert_eq!(field.as_grid()[(1, 2)], cols + 2);
+    }
+
+    #[test]
+    fn subfield() {
+        let field =
+            Field::new_from_fun(4., 4., 0.25, |p| (p.x * 3.).sin() + (p.y * 2.).cos()).unwrap();
+        let sub = field.subfield(3..9, 2..8);
+        assert_eq!(sub.as_grid().size(), (6, 6));
+        assert_eq!(sub.res(), field.res());
+
+        let cols = field.as_grid().cols();
+        let (origin, _) = field.iter_vertices().nth(2 * cols + 3).unwrap();
+        // inner points, away from the edges where the subfield wraps
+        for (u, v) in [(0.5, 0.5), (1.2, 0.3), (0.7, 1.1)] {
+            let p = point![u, v];
+            assert!((sub.value(p) - field.value(p + origin.coords)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn wrapping_subfield() {
+        let field = Field::new_filled(4., 4., 0.5, 0.).unwrap();
+        let cols = field.as_grid().cols();
+        let _ = field.subfield(cols - 2..cols + 2, 0..4);
+    }
+
+    #[test]
+    #[should_panic]
+    fn odd_rows_subfield() {
+        let field = Field::new_filled(4., 4., 0.5, 0.).unwrap();
+        let _ = field.subfield(0..8, 0..3);
+    }
+
+    #[test]
+    fn flow_on_tilted_plane() {
+        let field = Field::new_from_fun(4., 4., 0.5, |p| p.x)
+            .unwrap()
+            .with_boundary(BoundaryMode::Clamp);
+        let flow = field.flow_accumulation();
+        let (rows, cols) = flow.as_grid().size();
+        for row in 0..rows {
+            // the water flows along the rows toward the low edge, then down the edge
+            for col in 1..cols {
+                assert_eq!(flow.as_grid()[(row, col)], (cols - col) as f64);
+            }
+            assert_eq!(flow.as_grid()[(row, 0)], (cols * (rows - row)) as f64);
+        }
+    }
+
+    #[test]
+    fn flat_horizon() {
+        let field = Field::new_filled(4., 4., 0.5, 1.).unwrap();
+        let horizon = field.horizon(point![1., 2., 1.], 16);
+        assert_eq!(horizon.len(), 16);
+        assert!(horizon.iter().all(|&angle| angle == 0.));
+
+        // from above the terrain is below the horizon
+        let horizon = field.horizon(point![1., 2., 2.], 8);
+        assert!(horizon.iter().all(|&angle| angle < 0.));
+    }
+
+    #[test]
+    fn ridge_on_horizon() {
+        // a ridge at x = 3, one unit high
+        let field = Field::new_from_fun(6., 6., 0.1, |p| (1. - (p.x - 3.).abs()).max(0.)).unwrap();
+        let horizon = field.horizon(point![1., 3., 0.], 4);
+        // looking toward the ridge, its top is 2 units away
+        assert!((horizon[0] - 0.5f64.atan()).abs() < 0.05);
+    }
+
+    #[test]
+    fn blend_with_mask() {
+        let base = Field::new_from_fun(4., 4., 0.5, |p| p.x.sin()).unwrap();
+        let detail = Field::new_from_fun(4., 4., 0.5, |p| p.y.cos() * 3.).unwrap();
+
+        let ones = Field::new_filled(4., 4., 0.5, 1.).unwrap();
+        let blended = base.blend(&detail, &ones);
+        assert_eq!(blended.as_grid().flatten(), detail.as_grid().flatten());
+
+        let zeros = Field::new_filled(4., 4., 0.5, -2.).unwrap();
+        let blended = base.blend(&detail, &zeros);
+        assert_eq!(blended.as_grid().flatten(), base.as_grid().flatten());
+
+        let half = Field::new_filled(4., 4., 0.5, 0.5).unwrap();
+        let blended = base.blend(&detail, &half);
+        let mean = (base.clone() + detail.clone()).scale(0.5);
+        for (a, b) in blended.as_grid().iter().zip(mean.as_grid().iter()) {
+            assert!((a - b).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn blend_incompatible_fields() {
+        let a = Field::new_filled(4., 4., 0.5, 0.).unwrap();
+        let b = Field::new_filled(4., 4., 0.25, 0.).unwrap();
+        let _ = a.blend(&a, &b);
+    }
+
+    #[test]
+    fn local_max_gradient() {
+        let flat = Field::new_filled(4., 4., 0.5, 3.).unwrap();
+        for radius in [0, 1, 5, 100] {
+            assert_eq!(flat.local_max_gradient(point![1., 1.], radius), 0.);
+        }
+
+        // steep only on the right half
+        let field = Field::new_from_fun(8., 8., 0.25, |p| if p.x > 4. { p.x * 3. } else { 0. })
+            .unwrap()
+            .with_boundary(BoundaryMode::Clamp);
+        assert_eq!(field.local_max_gradient(point![1., 4.], 2), 0.);
+        assert!((field.local_max_gradient(point![6., 4.], 1) - 3.).abs() < 1e-9);
+        assert!(field.local_max_gradient(point![3.5, 4.], 4) <= field.max_gradient());
+    }
+
+    #[test]
+    fn exact_access() {
+        let field = Field::new_from_fun(4., 3., 0.5, |p| p.x + 10. * p.y).unwrap();
+        let (rows, cols) = field.as_grid().size();
+        for row in 0..rows {
+            for col in 0..cols {
+                assert_eq!(
+                    field.get_exact(col, row),
+                    Some(field.get(col as isize, row as isize))
+                );
+            }
+        }
+        assert_eq!(field.get_exact(cols, 0), None);
+        assert_eq!(field.get_exact(0, rows), None);
+        // the wrapping access goes around the tile
+        assert_eq!(field.get(cols as isize, 0), field.get(0, 0));
+
+        let mut field = field.with_boundary(BoundaryMode::Clamp);
+        assert_eq!(field.get(-3, 1), field.get(0, 1));
+        *field.get_exact_mut(1, 1).unwrap() = -1.;
+        assert_eq!(*field.get(1, 1), -1.);
+        assert!(field.get_exact_mut(cols + 1, 1).is_none());
+    }
+
+    #[test]
+    fn cached_gradients() {
+        let field = Field::new_from_fun(4., 3., 0.25, |p| (p.x * 2.).sin() * p.y.cos()).unwrap();
+        let cache = field.gradient_cache();
+        for i in 0..200 {
+            let pos = point![i as f64 * 0.037, i as f64 * 0.021];
+            assert_eq!(cache.gradient(pos), field.gradient(pos));
+            assert_eq!(cache.normal(pos), field.normal(pos));
+        }
+        // nearby points share the triangles
+        assert!(cache.len() < 200);
+    }
+
+    #[test]
+    #[should_panic]
+    fn add_incompatible_fields() {
+        let _ = Field::new_filled(4., 4., 0.5, 1.).unwrap()
+            + Field::new_filled(4., 4., 0.25, 1.).unwrap();
+    }
+}
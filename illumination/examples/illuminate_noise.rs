@@ -1,4 +1,3 @@
-#![feature(unwrap_infallible)]
 #![feature(exit_status_error)]
 
 use std::{
@@ -11,10 +10,10 @@ use std::{
     process::Stdio,
 };
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use field::Field;
 use illumination::{Illumination, SunSetup};
-use image::{GrayImage, Luma};
+use image::{DynamicImage, GrayImage, Luma, Rgb, RgbImage};
 use nalgebra::{point, vector};
 use noise::{NoiseFn, Simplex};
 use rand::{thread_rng, Rng};
@@ -53,6 +52,24 @@ struct Args {
     #[clap(long, default_value = "10")]
     /// Ambient illumination, in percentage of total energy flux
     ambient: f64,
+    #[clap(long, default_value = "0")]
+    /// Moonlight, as a fraction of the solar constant
+    moon_fraction: f64,
+    #[clap(long, default_value = "0")]
+    /// Axial tilt of the planet, in degrees
+    axial_tilt: f64,
+    #[clap(long, default_value = "0")]
+    /// Position in the year, in degrees from the spring equinox
+    season_phase: f64,
+    #[clap(long, default_value = "0")]
+    /// Angular radius of the sun disk, in degrees
+    sun_angular_radius: f64,
+    #[clap(long, default_value = "1")]
+    /// Number of shadow rays toward the sun disk
+    shadow_samples: NonZeroUsize,
+    #[clap(long, default_value = "0")]
+    /// Optical depth of the atmosphere at the zenith
+    turbidity: f64,
 
     #[clap(long, short)]
     /// Output file for the noise map
@@ -62,10 +79,28 @@ struct Args {
     /// Resolution of the output, in dots for unit
     dpu: NonZeroUsize,
 
+    #[clap(long, value_enum, default_value = "gray")]
+    /// Color map used for the illumination
+    colormap: ColorMap,
+    #[clap(long, default_value = "1")]
+    /// Multiplier of the illumination before it is clamped, raise to see dim scenes
+    exposure: f64,
+    #[clap(long, default_value = "1")]
+    /// Gamma of the illumination images, above 1 brightens the shadows
+    gamma: f64,
+
     #[clap(subcommand)]
     command: Command,
 }
 
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ColorMap {
+    /// Brighter is more illuminated
+    Gray,
+    /// Perceptually uniform ramp from dark blue to yellow
+    Viridis,
+}
+
 #[derive(Debug, Clone, Subcommand)]
 enum Command {
     /// Photograph a single illuminated frame
@@ -104,7 +139,16 @@ fn main() {
         solar_constant,
         noise_map,
         ambient,
+        moon_fraction,
+        axial_tilt,
+        season_phase,
+        sun_angular_radius,
+        shadow_samples,
+        turbidity,
         dpu,
+        colormap,
+        exposure,
+        gamma,
         command,
     } = Args::parse();
 
@@ -133,7 +177,8 @@ fn main() {
         let v = v / 2.;
         // sampling the 4D noise map
         noise.get([u.x, u.y, v.x, v.y])
-    });
+    })
+    .expect("Invalid field shape");
 
     if let Some(noise_map) = noise_map {
         img_from_map(&map, dpu)
@@ -146,8 +191,15 @@ fn main() {
         latitude,
         solar_constant,
         ambient,
+        moon_fraction,
+        axial_tilt,
+        season_phase,
+        sun_angular_radius,
+        shadow_samples,
+        turbidity,
+        ..Default::default()
     })
-    .into_ok();
+    .expect("Invalid sun setup");
 
     match command {
         Command::Frame {
@@ -155,9 +207,16 @@ fn main() {
             illuminate_map,
         } => {
             let illuminated = illumination.illuminate(&map, time);
-            img_from_illumination(&illuminated, illumination.solar_constant, dpu)
-                .save(illuminate_map)
-                .expect("Cannot save map image")
+            img_from_illumination(
+                &illuminated,
+                illumination.solar_constant,
+                dpu,
+                colormap,
+                exposure,
+                gamma,
+            )
+            .save(illuminate_map)
+            .expect("Cannot save map image")
         }
         Command::Video {
             framerate,
@@ -184,16 +243,18 @@ fn main() {
                 .spawn()
                 .expect("Cannot launch ffmpeg");
             let mut frame_buffer = Vec::<u8>::new();
+            // the map is static, so the raycaster can be shared by all frames
+            let caster = map.raycaster(Default::default());
 
             for f in 0..frames {
                 let time = Time::ZERO - illumination.day_lenght * 0.5
                     + (illumination.day_lenght * f) / frames;
 
-                let illuminated = illumination.illuminate(&map, time);
+                let illuminated = illumination.illuminate_with(&caster, time);
 
                 // sending to ffmpeg
                 frame_buffer.clear();
-                img_from_illumination(&illuminated, solar_constant, dpu)
+                img_from_illumination(&illuminated, solar_constant, dpu, colormap, exposure, gamma)
                     .write_to(&mut Cursor::new(&mut frame_buffer), image::ImageFormat::Bmp)
                     .expect("Cannot save frame as image");
                 ffmpeg
@@ -231,19 +292,105 @@ fn img_from_map(map: &Field<f64>, dpu: NonZeroUsize) -> GrayImage {
 }
 
 /// Generate an image from a grid of illumintions
+///
+/// The gray colormap gives a gray image, the others an RGB one
 fn img_from_illumination(
     illumination: &Field<f64>,
     solar_constant: f64,
     dpu: NonZeroUsize,
-) -> GrayImage {
+    colormap: ColorMap,
+    exposure: f64,
+    gamma: f64,
+) -> DynamicImage {
     let res_x = (illumination.tile_x() * dpu.get() as f64) as u32;
     let res_y = (illumination.tile_y() * dpu.get() as f64) as u32;
 
-    GrayImage::from_fn(res_x, res_y, |x, y| {
+    let value = |x: u32, y: u32| {
         let x = illumination.tile_x() * (x as f64 / res_x as f64);
         let y = illumination.tile_y() * (1. - y as f64 / res_y as f64);
 
-        let value = (u8::MAX as f64 * (illumination.value(point![x, y]) / solar_constant)) as u8;
-        Luma([value])
-    })
+        illumination.value(point![x, y])
+    };
+    match colormap {
+        ColorMap::Gray => GrayImage::from_fn(res_x, res_y, |x, y| {
+            Luma([tonemap(value(x, y), solar_constant, exposure, gamma)])
+        })
+        .into(),
+        ColorMap::Viridis => RgbImage::from_fn(res_x, res_y, |x, y| {
+            ramp(exposed(value(x, y), solar_constant, exposure, gamma))
+        })
+        .into(),
+    }
+}
+
+/// Map an illumination to [0, 1], applying exposure and gamma
+///
+/// Exposure and gamma of 1 give the plain fraction of the solar constant
+fn exposed(value: f64, solar_constant: f64, exposure: f64, gamma: f64) -> f64 {
+    (value / solar_constant * exposure)
+        .clamp(0., 1.)
+        .powf(1. / gamma)
+}
+
+/// Map an illumination to a gray level, see [`exposed`]
+fn tonemap(value: f64, solar_constant: f64, exposure: f64, gamma: f64) -> u8 {
+    (u8::MAX as f64 * exposed(value, solar_constant, exposure, gamma)) as u8
+}
+
+/// Map a value in [0, 1] to the viridis color ramp
+///
+/// The ramp is linearly interpolated between samples of the original one
+fn ramp(t: f64) -> Rgb<u8> {
+    const VIRIDIS: [[f64; 3]; 9] = [
+        [68., 1., 84.],
+        [71., 44., 122.],
+        [59., 81., 139.],
+        [44., 113., 142.],
+        [33., 144., 141.],
+        [39., 173., 129.],
+        [92., 200., 99.],
+        [170., 220., 50.],
+        [253., 231., 37.],
+    ];
+    let t = t.clamp(0., 1.) * (VIRIDIS.len() - 1) as f64;
+    let i = (t as usize).min(VIRIDIS.len() - 2);
+    let f = t - i as f64;
+    let [a, b] = [VIRIDIS[i], VIRIDIS[i + 1]];
+    Rgb([0, 1, 2].map(|c| (a[c] + (b[c] - a[c]) * f).round() as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ramp, tonemap};
+
+    #[test]
+    fn tonemap_known_points() {
+        // the defaults are linear
+        assert_eq!(tonemap(0., 1000., 1., 1.), 0);
+        assert_eq!(tonemap(500., 1000., 1., 1.), 127);
+        assert_eq!(tonemap(1000., 1000., 1., 1.), 255);
+        // out of range values are clamped
+        assert_eq!(tonemap(-10., 1000., 1., 1.), 0);
+        assert_eq!(tonemap(2000., 1000., 1., 1.), 255);
+
+        assert_eq!(tonemap(250., 1000., 4., 1.), 255);
+        assert_eq!(tonemap(250., 1000., 1., 2.), 127);
+    }
+
+    #[test]
+    fn ramp_luminance_is_monotonic() {
+        let luminance = |t| {
+            let [r, g, b] = ramp(t).0.map(|c| c as f64);
+            0.2126 * r + 0.7152 * g + 0.0722 * b
+        };
+        let mut last = luminance(0.);
+        for i in 1..=100 {
+            let current = luminance(i as f64 / 100.);
+            assert!(current >= last);
+            last = current;
+        }
+        // out of range values are clamped
+        assert_eq!(ramp(-1.), ramp(0.));
+        assert_eq!(ramp(2.), ramp(1.));
+    }
 }
@@ -1,17 +1,15 @@
-#![feature(duration_constants)]
-#![feature(never_type)]
+use std::{f64::consts::PI, num::NonZeroUsize};
 
-use std::f64::consts::PI;
-
-use field::Field;
-use nalgebra::{point, Unit, UnitVector3, Vector3};
+use field::{Field, Raycaster};
+use nalgebra::{point, vector, Point2, Point3, Unit, UnitVector3, Vector3};
 use serde_with::serde_as;
 use sim_time::{Duration, Time};
 
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 #[serde_as]
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[serde(default)]
 pub struct SunSetup {
     /// Duration of the day
@@ -23,6 +21,60 @@ pub struct SunSetup {
     pub solar_constant: f64,
     /// Ambient illumination, in percentage of total energy flux
     pub ambient: f64,
+    /// Moonlight, as a fraction of the solar constant
+    ///
+    /// It replaces the ambient term when the sun is below the horizon
+    pub moon_fraction: f64,
+    /// Axial tilt of the planet, in degrees
+    pub axial_tilt: f64,
+    /// Position in the year, in degrees
+    ///
+    /// 0 is the spring equinox, 90 the summer solstice, 180 the autumn equinox
+    /// and 270 the winter solstice
+    pub season_phase: f64,
+    /// Angular radius of the sun disk, in degrees
+    ///
+    /// When nonzero, shadows get a penumbra
+    pub sun_angular_radius: f64,
+    /// Number of shadow rays cast toward the sun disk for each vertex
+    ///
+    /// The cost of the shadow calculation grows linearly with this.
+    /// It is ignored if the sun has no radius.
+    pub shadow_samples: NonZeroUsize,
+    /// Color of the sun at the zenith, as linear RGB
+    pub sun_color: [f64; 3],
+    /// Color of the sun at the horizon, as linear RGB
+    ///
+    /// The sun color is linearly interpolated with the sine of the elevation
+    pub sunset_color: [f64; 3],
+    /// Color of the ambient light, as linear RGB
+    pub sky_color: [f64; 3],
+    /// Optical depth of the atmosphere at the zenith
+    ///
+    /// The direct sunlight is attenuated by `exp(-turbidity * (air_mass - 1))`, so it is
+    /// unchanged with the sun at the zenith and dimmer toward the horizon
+    pub turbidity: f64,
+    /// How the path of the sun is calculated
+    pub sun_model: SunModel,
+    /// Number of rays cast toward the sky for each vertex, to find how much of it is visible
+    ///
+    /// The ambient light is scaled by the visible fraction of the sky, so it is dimmer in pits
+    /// and valleys. This is expensive: every illumination casts this many rays for each vertex,
+    /// on top of the shadow rays. With 0 the ambient light is the same everywhere.
+    pub sky_occlusion_samples: usize,
+}
+
+/// Model of the path of the sun in the sky
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SunModel {
+    /// The sun runs every day along the same circle, set by the declination at the
+    /// given season phase
+    #[default]
+    Planar,
+    /// The sun position is calculated from the hour angle, latitude and declination,
+    /// with the declination following the seasons along a year of 365 days
+    Spherical,
 }
 
 impl Default for SunSetup {
@@ -32,22 +84,126 @@ impl Default for SunSetup {
             latitude: 45.,
             solar_constant: 615.15,
             ambient: 10.,
+            moon_fraction: 0.,
+            axial_tilt: 0.,
+            season_phase: 0.,
+            sun_angular_radius: 0.,
+            shadow_samples: NonZeroUsize::MIN,
+            sun_color: [1., 1., 1.],
+            sunset_color: [1., 0.5, 0.25],
+            sky_color: [0.6, 0.75, 1.],
+            turbidity: 0.,
+            sun_model: SunModel::Planar,
+            sky_occlusion_samples: 0,
         }
     }
 }
 
+impl SunSetup {
+    /// Start building a setup from the default one
+    pub fn builder() -> SunSetupBuilder {
+        SunSetupBuilder::default()
+    }
+}
+
+/// Fluent builder for a [`SunSetup`]
+///
+/// Fields without a setter can be set on the [`SunSetup`] returned by [`SunSetupBuilder::setup`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SunSetupBuilder(SunSetup);
+
+impl SunSetupBuilder {
+    /// Set the latitude, in degrees
+    pub fn latitude(mut self, latitude: f64) -> Self {
+        self.0.latitude = latitude;
+        self
+    }
+    /// Set the duration of the day
+    pub fn day_length(mut self, day_lenght: Duration) -> Self {
+        self.0.day_lenght = day_lenght;
+        self
+    }
+    /// Set the solar constant, in W/m^2
+    pub fn solar_constant(mut self, solar_constant: f64) -> Self {
+        self.0.solar_constant = solar_constant;
+        self
+    }
+    /// Set the ambient illumination, in percentage of total energy flux
+    pub fn ambient(mut self, ambient: f64) -> Self {
+        self.0.ambient = ambient;
+        self
+    }
+
+    /// The setup built so far, without validation
+    pub fn setup(self) -> SunSetup {
+        self.0
+    }
+
+    /// Validate the setup and build the illumination
+    pub fn build(self) -> Result<Illumination, SunSetupError> {
+        Illumination::new(self.0)
+    }
+}
+
+/// Invalid value in a [`SunSetup`]
+#[derive(Debug, Clone, Copy, PartialEq, Error)]
+pub enum SunSetupError {
+    #[error("The day lenght must be positive")]
+    ZeroDayLenght,
+    #[error("Latitude {0} is outside [-90, 90]")]
+    Latitude(f64),
+    #[error("Solar constant {0} is negative")]
+    NegativeSolarConstant(f64),
+    #[error("Ambient illumination {0} is outside [0, 100]")]
+    Ambient(f64),
+    #[error("Moon fraction {0} is outside [0, 1]")]
+    MoonFraction(f64),
+    #[error("Sun angular radius {0} is outside [0, 90)")]
+    SunAngularRadius(f64),
+    #[error("Turbidity {0} is negative")]
+    NegativeTurbidity(f64),
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Illumination {
     /// Duration of the day
     pub day_lenght: Duration,
-    /// Highest point of the sun in the sky
+    /// Highest point of the sun in the sky at the equinox
     pub solar_noon: UnitVector3<f64>,
-    /// Point where the sun raises
+    /// Point where the sun raises at the equinox
     pub sunrise: UnitVector3<f64>,
+    /// Direction of the celestial north pole
+    pub celestial_pole: UnitVector3<f64>,
+    /// Latitude, in radians
+    pub latitude: f64,
+    /// Axial tilt of the planet, in radians
+    pub axial_tilt: f64,
+    /// Position in the year at [`Time::ZERO`], in radians
+    pub season_phase: f64,
+    /// How the path of the sun is calculated
+    pub sun_model: SunModel,
+    /// Declination of the sun at [`Time::ZERO`], in radians
+    pub declination: f64,
+    /// Angular radius of the sun disk, in radians
+    pub sun_angular_radius: f64,
+    /// Number of shadow rays cast toward the sun disk
+    pub shadow_samples: NonZeroUsize,
+    /// Color of the sun at the zenith
+    pub sun_color: Vector3<f64>,
+    /// Color of the sun at the horizon
+    pub sunset_color: Vector3<f64>,
+    /// Color of the ambient light
+    pub sky_color: Vector3<f64>,
+    /// Optical depth of the atmosphere at the zenith
+    pub turbidity: f64,
     /// Solar constant, in W/m^2
     pub solar_constant: f64,
     /// Ambient illumination [0-1]
     pub ambient: f64,
+    /// Moonlight, as a fraction of the solar constant [0-1]
+    pub moon_fraction: f64,
+    /// Number of rays cast toward the sky to scale the ambient light
+    pub sky_occlusion_samples: usize,
 }
 
 impl Illumination {
@@ -57,63 +213,841 @@ impl Illumination {
             latitude,
             solar_constant,
             ambient,
+            moon_fraction,
+            axial_tilt,
+            season_phase,
+            sun_angular_radius,
+            shadow_samples,
+            sun_color,
+            sunset_color,
+            sky_color,
+            turbidity,
+            sun_model,
+            sky_occlusion_samples,
         }: SunSetup,
-    ) -> Result<Self, !> {
+    ) -> Result<Self, SunSetupError> {
+        if day_lenght == Duration::ZERO {
+            return Err(SunSetupError::ZeroDayLenght);
+        }
+        if !(-90. ..=90.).contains(&latitude) {
+            return Err(SunSetupError::Latitude(latitude));
+        }
+        if !(0. ..=f64::INFINITY).contains(&solar_constant) {
+            return Err(SunSetupError::NegativeSolarConstant(solar_constant));
+        }
+        if !(0. ..=100.).contains(&ambient) {
+            return Err(SunSetupError::Ambient(ambient));
+        }
+        if !(0. ..=1.).contains(&moon_fraction) {
+            return Err(SunSetupError::MoonFraction(moon_fraction));
+        }
+        if !(0. ..90.).contains(&sun_angular_radius) {
+            return Err(SunSetupError::SunAngularRadius(sun_angular_radius));
+        }
+        if !(0. ..=f64::INFINITY).contains(&turbidity) {
+            return Err(SunSetupError::NegativeTurbidity(turbidity));
+        }
+
         let latitude = latitude * (PI / 180.);
+        let axial_tilt = axial_tilt * (PI / 180.);
+        let season_phase = season_phase * (PI / 180.);
+        let declination = axial_tilt * season_phase.sin();
         Ok(Illumination {
             day_lenght,
+            latitude,
+            axial_tilt,
+            season_phase,
+            sun_model,
             solar_noon: UnitVector3::new_unchecked(
                 -Vector3::<f64>::y() * latitude.sin() + Vector3::z() * latitude.cos(),
             ),
             sunrise: Vector3::x_axis(),
+            celestial_pole: UnitVector3::new_unchecked(
+                Vector3::<f64>::y() * latitude.cos() + Vector3::z() * latitude.sin(),
+            ),
+            declination,
+            sun_angular_radius: sun_angular_radius * (PI / 180.),
+            shadow_samples,
+            sun_color: sun_color.into(),
+            sunset_color: sunset_color.into(),
+            sky_color: sky_color.into(),
+            turbidity,
             solar_constant,
             ambient: ambient / 100.,
+            moon_fraction,
+            sky_occlusion_samples,
         })
     }
 
-    pub fn illuminate(&self, map: &Field<f64>, time: Time) -> Field<f64> {
+    /// Position of the sun in the sky at a given time
+    ///
+    /// The sun runs along a circle parallel to the celestial equator, offset toward the
+    /// pole by the declination: this changes both the peak elevation and the day lenght.
+    /// With [`SunModel::Spherical`] the declination also changes along the year.
+    pub fn sun_position(&self, time: Time) -> UnitVector3<f64> {
         // angle of the sun from the noon
-        let sun_theta = (time - Time::ZERO)
-            .rem_euclid(self.day_lenght.into())
-            .as_time_delta()
-            .div_f(self.day_lenght.into())
-            * (2. * PI);
-        // position of the sun in the sky
-        let sun_pos = Unit::new_unchecked(
-            sun_theta.sin() * self.sunrise.into_inner()
-                + sun_theta.cos() * self.solar_noon.into_inner(),
+        let sun_theta =
+            (time - Time::ZERO).rem_euclid(self.day_lenght.into()) / self.day_lenght * (2. * PI);
+        match self.sun_model {
+            SunModel::Planar => self.planar_sun_position(sun_theta),
+            SunModel::Spherical => self.spherical_sun_position(sun_theta, time),
+        }
+    }
+
+    /// Declination of the sun at a given time, in radians
+    ///
+    /// It is constant with [`SunModel::Planar`]
+    pub fn declination_at(&self, time: Time) -> f64 {
+        match self.sun_model {
+            SunModel::Planar => self.declination,
+            SunModel::Spherical => {
+                let year = self.day_lenght * 365;
+                let year_theta = (time - Time::ZERO).rem_euclid(year.into()) / year;
+                self.axial_tilt * (self.season_phase + year_theta * (2. * PI)).sin()
+            }
+        }
+    }
+
+    /// Position of the sun from the elevation and azimuth given by the hour angle
+    fn spherical_sun_position(&self, hour_angle: f64, time: Time) -> UnitVector3<f64> {
+        let declination = self.declination_at(time);
+        let (sin_lat, cos_lat) = self.latitude.sin_cos();
+        let (sin_dec, cos_dec) = declination.sin_cos();
+
+        let elevation = (sin_lat * sin_dec + cos_lat * cos_dec * hour_angle.cos()).asin();
+        // azimuth from the north, toward east
+        let azimuth = f64::atan2(
+            cos_dec * hour_angle.sin(),
+            sin_dec * cos_lat - cos_dec * hour_angle.cos() * sin_lat,
         );
+        Unit::new_normalize(Vector3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.cos() * azimuth.cos(),
+            elevation.sin(),
+        ))
+    }
+
+    /// Position of the sun on the circle set by the fixed declination
+    fn planar_sun_position(&self, sun_theta: f64) -> UnitVector3<f64> {
+        Unit::new_unchecked(
+            self.declination.cos()
+                * (sun_theta.sin() * self.sunrise.into_inner()
+                    + sun_theta.cos() * self.solar_noon.into_inner())
+                + self.declination.sin() * self.celestial_pole.into_inner(),
+        )
+    }
+
+    /// Illuminate a map with the sun alone
+    pub fn illuminate(&self, map: &Field<f64>, time: Time) -> Field<f64> {
+        illuminate(map, time, &[self])
+    }
+
+    /// Illuminate the map of a prebuilt raycaster with the sun alone
+    ///
+    /// This gives the same result of [`Illumination::illuminate`], but the raycaster
+    /// can be built once and reused when the same map is illuminated at many times.
+    pub fn illuminate_with(&self, caster: &Raycaster, time: Time) -> Field<f64> {
+        illuminate_with(caster, time, &[self])
+    }
+
+    /// Illuminate a map with the sun, calculating the color of the light
+    ///
+    /// The direct light takes the color of the sun, reddening toward the horizon,
+    /// while the ambient light takes the color of the sky
+    pub fn illuminate_rgb(&self, map: &Field<f64>, time: Time) -> Field<Vector3<f64>> {
+        let sun_color = self.sun_color(time);
+        shade(
+            &map.raycaster(Default::default()),
+            time,
+            &[self],
+            |ambient| self.sky_color * ambient,
+            |total, energy| total + sun_color * energy,
+        )
+    }
 
+    /// Energy flux reaching a surface with any orientation, in W/m^2
+    ///
+    /// This is the illumination of a surface placed at `pos`, like a solar panel on the terrain,
+    /// with its own `normal` instead of the one of the field.
+    /// The shadows are cast from `pos` on the field of `caster`.
+    /// The direct light hitting the back of the surface is not counted, while the ambient light
+    /// is added whatever the orientation, as it is for the terrain, scaled only by the
+    /// visible sky if [`SunSetup::sky_occlusion_samples`] is set.
+    pub fn flux_on_surface(
+        &self,
+        pos: Point3<f64>,
+        normal: UnitVector3<f64>,
+        time: Time,
+        caster: &Raycaster,
+    ) -> f64 {
+        let direct = self.direct(time).map_or(0., |(dir, intensity)| {
+            direct_energy(
+                caster,
+                pos,
+                &normal,
+                &shadow_rays(self, dir),
+                &(dir.into_inner() * intensity),
+            )
+            .max(0.)
+        });
+        let ambient = match self.sky_occlusion_samples {
+            0 => LightSource::ambient(self, time),
+            samples => {
+                LightSource::ambient(self, time) * sky_visibility(caster, pos, &sky_rays(samples))
+            }
+        };
+        direct + ambient
+    }
+
+    /// Check if a point is directly lit by the sun
+    ///
+    /// A single shadow ray is cast toward the center of the sun, ignoring its radius, from
+    /// the point at `height` above `pos`.
+    /// When the sun is below the horizon the point is never lit, even if the ray would
+    /// escape the map.
+    pub fn is_lit(&self, caster: &Raycaster, pos: Point2<f64>, height: f64, time: Time) -> bool {
+        let sun_pos = self.sun_position(time);
+        sun_pos.z >= 0. && reaches_sky(caster, point![pos.x, pos.y, height], &sun_pos)
+    }
+
+    /// Mask of the vertices of a map in the shadow of the sun
+    ///
+    /// Each vertex is `true` if the center of the sun is hidden, using the same shadow ray
+    /// of [`Illumination::is_lit`]. When the sun is below the horizon the whole map is `true`.
+    pub fn shadow_map(&self, map: &Field<f64>, time: Time) -> Field<bool> {
+        let sun_pos = self.sun_position(time);
+        if sun_pos.z < 0. {
+            return map.map_ref(|_| true);
+        }
+        let caster = map.raycaster(Default::default());
+        map.map_ref_with_coords(|pos, &height| {
+            !reaches_sky(&caster, point![pos.x, pos.y, height], &sun_pos)
+        })
+    }
+
+    /// Fraction of the direct sunlight that crosses the atmosphere
+    ///
+    /// The air mass is approximated as `1 / sin(elevation)`, as for a flat atmosphere,
+    /// and clamped to the air mass at the horizon to avoid diverging.
+    fn extinction(&self, sin_elevation: f64) -> f64 {
+        const HORIZON_AIR_MASS: f64 = 38.;
+        let air_mass = 1. / sin_elevation.max(1. / HORIZON_AIR_MASS);
+        (-self.turbidity * (air_mass - 1.)).exp()
+    }
+
+    /// Color of the sun at a given time
+    fn sun_color(&self, time: Time) -> Vector3<f64> {
+        let t = self.sun_position(time).z.clamp(0., 1.);
+        self.sunset_color + (self.sun_color - self.sunset_color) * t
+    }
+
+    /// Total energy received in a day, in J/m^2
+    ///
+    /// The illumination is sampled at `samples` evenly spaced times, starting from noon,
+    /// and each sample is weighted by the sampling interval.
+    /// More samples reduce the quadrature error, mostly concentrated around sunrise and sunset.
+    pub fn integrate_day(&self, map: &Field<f64>, samples: NonZeroUsize) -> Field<f64> {
+        let samples = samples.get() as u64;
+        let interval = self.day_lenght / Duration::SECOND / samples as f64;
+
+        let caster = map.raycaster(Default::default());
+        (0..samples)
+            .map(|i| self.illuminate_with(&caster, Time::ZERO + (self.day_lenght * i) / samples))
+            .reduce(|total, sample| total + sample)
+            .unwrap()
+            .scale(interval)
+    }
+}
+
+impl LightSource for Illumination {
+    fn direct(&self, time: Time) -> Option<(UnitVector3<f64>, f64)> {
+        let sun_pos = self.sun_position(time);
         if sun_pos.z < 0. {
             // sun is underground
-            return map.clone().map(|_| 0.);
+            return None;
+        }
+        Some((
+            sun_pos,
+            self.solar_constant * (1. - self.ambient) * self.extinction(sun_pos.z),
+        ))
+    }
+
+    fn ambient(&self, time: Time) -> f64 {
+        let sun_pos = self.sun_position(time);
+        if sun_pos.z < 0. {
+            // only the moon is shining
+            self.moon_fraction * self.solar_constant
+        } else {
+            self.solar_constant * sun_pos.z * self.ambient
         }
+    }
 
-        // energy vector
-        let energy_flux = self.solar_constant * sun_pos.into_inner();
+    fn angular_radius(&self) -> f64 {
+        self.sun_angular_radius
+    }
+
+    fn shadow_samples(&self) -> NonZeroUsize {
+        self.shadow_samples
+    }
+
+    fn sky_occlusion_samples(&self) -> usize {
+        self.sky_occlusion_samples
+    }
+}
+
+/// Something that sheds light on a map
+pub trait LightSource {
+    /// Direction toward the source and its energy flux, in W/m^2
+    ///
+    /// Returns `None` if the source is not shining at the given time
+    fn direct(&self, time: Time) -> Option<(UnitVector3<f64>, f64)>;
+
+    /// Diffuse light reaching every point of the map, in W/m^2
+    fn ambient(&self, _time: Time) -> f64 {
+        0.
+    }
+
+    /// Angular radius of the source, in radians
+    fn angular_radius(&self) -> f64 {
+        0.
+    }
+
+    /// Number of shadow rays to cast toward the source disk
+    fn shadow_samples(&self) -> NonZeroUsize {
+        NonZeroUsize::MIN
+    }
+
+    /// Number of rays to cast toward the sky to scale the ambient light
+    ///
+    /// With 0 the ambient light reaches every point of the map unchanged
+    fn sky_occlusion_samples(&self) -> usize {
+        0
+    }
+}
+
+/// Directions of the shadow rays toward a source disk
+///
+/// The directions are spread on the disk with a sunflower pattern, so the result is deterministic.
+/// A point source gets a single ray.
+fn shadow_rays(source: &dyn LightSource, dir: UnitVector3<f64>) -> Vec<Vector3<f64>> {
+    let radius = source.angular_radius();
+    let samples = source.shadow_samples().get();
+    if radius == 0. || samples == 1 {
+        return vec![dir.into_inner()];
+    }
 
-        let ambient = energy_flux.z * self.ambient;
-        let energy_flux = energy_flux * (1. - self.ambient);
+    // basis orthogonal to the direction
+    let u = dir
+        .cross(&if dir.x.abs() < 0.9 {
+            Vector3::x()
+        } else {
+            Vector3::y()
+        })
+        .normalize();
+    let v = dir.cross(&u);
+
+    let golden_angle = PI * (3. - 5f64.sqrt());
+    (0..samples)
+        .map(|k| {
+            let r = radius * ((k as f64 + 0.5) / samples as f64).sqrt();
+            let phi = k as f64 * golden_angle;
+            dir.into_inner() * r.cos() + (u * phi.cos() + v * phi.sin()) * r.sin()
+        })
+        .collect()
+}
+
+/// A fixed light source, infinitely far away
+#[derive(Debug, Clone, Copy)]
+pub struct PointAtInfinity {
+    /// Direction toward the source
+    pub dir: UnitVector3<f64>,
+    /// Energy flux, in W/m^2
+    pub intensity: f64,
+}
+
+impl LightSource for PointAtInfinity {
+    fn direct(&self, _time: Time) -> Option<(UnitVector3<f64>, f64)> {
+        Some((self.dir, self.intensity))
+    }
+}
+
+/// Illuminate a map with multiple light sources, summing their contributions
+///
+/// Shadows are calculated independently for each source, so every lit source
+/// costs [`LightSource::shadow_samples`] shadow rays for each vertex.
+/// Sources with a radius are only partially visible in the penumbra.
+pub fn illuminate(map: &Field<f64>, time: Time, sources: &[&dyn LightSource]) -> Field<f64> {
+    illuminate_with(&map.raycaster(Default::default()), time, sources)
+}
+
+/// Illuminate the map of a prebuilt raycaster with multiple light sources
+///
+/// See [`illuminate`]. Reusing the raycaster saves its setup when illuminating
+/// the same map many times.
+pub fn illuminate_with(caster: &Raycaster, time: Time, sources: &[&dyn LightSource]) -> Field<f64> {
+    shade(
+        caster,
+        time,
+        sources,
+        |ambient| ambient,
+        |total, energy| total + energy,
+    )
+}
+
+/// Calculate the light reaching each vertex of the map
+///
+/// The value of each vertex is built from the total ambient light, then each shining source
+/// adds its direct energy.
+fn shade<U>(
+    caster: &Raycaster,
+    time: Time,
+    sources: &[&dyn LightSource],
+    ambient: impl Fn(f64) -> U,
+    direct: impl Fn(U, f64) -> U,
+) -> Field<U> {
+    // ambient light reaching every vertex, and the one scaled by the visible sky
+    let flat_ambient: f64 = sources
+        .iter()
+        .filter(|s| s.sky_occlusion_samples() == 0)
+        .map(|s| s.ambient(time))
+        .sum();
+    let occluded_ambient: Vec<_> = sources
+        .iter()
+        .filter(|s| s.sky_occlusion_samples() != 0)
+        .map(|s| (s.ambient(time), sky_rays(s.sky_occlusion_samples())))
+        .collect();
+    // shadow rays and energy vectors of the sources that are shining
+    let lights: Vec<_> = sources
+        .iter()
+        .filter_map(|s| {
+            s.direct(time)
+                .map(|(dir, intensity)| (shadow_rays(*s, dir), dir.into_inner() * intensity))
+        })
+        .collect();
+
+    let map = caster.field();
+
+    if lights.is_empty() && occluded_ambient.is_empty() {
+        return map.map_ref(|_| ambient(flat_ambient));
+    }
+
+    map.map_ref_with_coords(|pos, &height| {
+        let point = point![pos.x, pos.y, height];
+        let ambient_energy = flat_ambient
+            + occluded_ambient
+                .iter()
+                .map(|(energy, rays)| energy * sky_visibility(caster, point, rays))
+                .sum::<f64>();
+        let normal = map.normal(pos);
+        lights
+            .iter()
+            .map(|(rays, energy_flux)| direct_energy(caster, point, &normal, rays, energy_flux))
+            .fold(ambient(ambient_energy), &direct)
+    })
+}
+
+/// Check if a ray from `pos` escapes the map
+///
+/// The ray starts a bit away from `pos`, so it does not hit the surface it starts from.
+fn reaches_sky(caster: &Raycaster, pos: Point3<f64>, dir: &Vector3<f64>) -> bool {
+    caster
+        .cast(pos + dir * caster.field().res() * 0.001, *dir)
+        .is_none()
+}
+
+/// Directions of the rays toward the sky, spread over the upper hemisphere
+///
+/// The directions follow a sunflower pattern on the disk, projected up on the hemisphere,
+/// so they are deterministic and denser toward the zenith, as the cosine weight of the
+/// light coming from the sky on a flat surface.
+fn sky_rays(samples: usize) -> Vec<Vector3<f64>> {
+    let golden_angle = PI * (3. - 5f64.sqrt());
+    (0..samples)
+        .map(|k| {
+            let r2 = (k as f64 + 0.5) / samples as f64;
+            let (r, phi) = (r2.sqrt(), k as f64 * golden_angle);
+            vector![r * phi.cos(), r * phi.sin(), (1. - r2).sqrt()]
+        })
+        .collect()
+}
+
+/// Fraction of the rays toward the sky that escape the map
+fn sky_visibility(caster: &Raycaster, pos: Point3<f64>, rays: &[Vector3<f64>]) -> f64 {
+    let visible = rays
+        .iter()
+        .filter(|dir| reaches_sky(caster, pos, dir))
+        .count();
+    visible as f64 / rays.len() as f64
+}
+
+/// Direct energy from a source reaching a surface, accounting for the shadows
+fn direct_energy(
+    caster: &Raycaster,
+    pos: Point3<f64>,
+    normal: &Vector3<f64>,
+    rays: &[Vector3<f64>],
+    energy_flux: &Vector3<f64>,
+) -> f64 {
+    // check how many rays reach the source
+    let visible = rays
+        .iter()
+        .filter(|dir| reaches_sky(caster, pos, dir))
+        .count();
+    if visible == rays.len() {
+        // directly illuminated by the source
+        normal.dot(energy_flux)
+    } else if visible == 0 {
+        // source is covered
+        0.
+    } else {
+        // penumbra
+        normal.dot(energy_flux) * (visible as f64 / rays.len() as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{f64::consts::PI, num::NonZeroUsize};
+
+    use field::Field;
+    use nalgebra::{point, vector, Unit, Vector3};
+    use sim_time::{Duration, Time};
+
+    use crate::{
+        illuminate, Illumination, LightSource, PointAtInfinity, SunModel, SunSetup, SunSetupError,
+    };
+
+    #[test]
+    fn moonlight_at_midnight() {
+        let setup = SunSetup {
+            moon_fraction: 0.01,
+            ..Default::default()
+        };
+        let illumination = Illumination::new(setup).unwrap();
+        let map = Field::new_filled(8., 8., 1., 0.).unwrap();
+
+        let midnight = Time::ZERO + setup.day_lenght / 2;
+        let illuminated = illumination.illuminate(&map, midnight);
+        // the ambient term follows the sun, so at night only the moon is left
+        let moon = 0.01 * setup.solar_constant;
+        assert_eq!(*illuminated.min_by(f64::total_cmp), moon);
+        assert_eq!(*illuminated.max_by(f64::total_cmp), moon);
+    }
+
+    #[test]
+    fn integrate_flat_day() {
+        let setup = SunSetup::default();
+        let illumination = Illumination::new(setup).unwrap();
+        let map = Field::new_filled(8., 8., 1., 0.).unwrap();
+
+        let energy = illumination.integrate_day(&map, NonZeroUsize::new(1000).unwrap());
+        // integral of the positive half of a sinusoid
+        let day = setup.day_lenght / Duration::SECOND;
+        let expected = setup.solar_constant * setup.latitude.to_radians().cos() * day / PI;
+        for (_, e) in energy.iter_vertices() {
+            assert!((e - expected).abs() / expected < 1e-2);
+        }
+    }
+
+    #[test]
+    fn opposing_lights() {
+        let map = Field::new_filled(8., 8., 1., 0.).unwrap();
+        let east = PointAtInfinity {
+            dir: Unit::new_normalize(vector![1., 0., 1.]),
+            intensity: 100.,
+        };
+        let west = PointAtInfinity {
+            dir: Unit::new_normalize(vector![-1., 0., 1.]),
+            intensity: 100.,
+        };
+
+        let illuminated = illuminate(&map, Time::ZERO, &[&east, &west]);
+        let expected = 200. / 2f64.sqrt();
+        for (_, e) in illuminated.iter_vertices() {
+            assert!((e - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn pointlike_sun_hard_shadows() {
+        let map =
+            Field::new_from_fun(8., 8., 0.5, |p| (p.x * 1.3).sin() + (p.y * 0.7).cos()).unwrap();
+        let time = Time::ZERO + Duration::HOUR * 4;
+
+        let hard = Illumination::new(SunSetup::default()).unwrap();
+        let pointlike = Illumination::new(SunSetup {
+            shadow_samples: NonZeroUsize::new(16).unwrap(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let hard = hard.illuminate(&map, time).into_grid();
+        let pointlike = pointlike.illuminate(&map, time).into_grid();
+        assert_eq!(hard.flatten(), pointlike.flatten());
+    }
+
+    #[test]
+    fn builder() {
+        assert_eq!(SunSetup::builder().setup(), SunSetup::default());
+
+        let setup = SunSetup::builder().latitude(10.).ambient(5.).setup();
+        assert_eq!(
+            setup,
+            SunSetup {
+                latitude: 10.,
+                ambient: 5.,
+                ..Default::default()
+            }
+        );
+
+        assert_eq!(
+            SunSetup::builder().latitude(100.).build().err(),
+            Some(SunSetupError::Latitude(100.))
+        );
+    }
+
+    #[test]
+    fn invalid_setups() {
+        for (setup, error) in [
+            (
+                SunSetup {
+                    day_lenght: Duration::ZERO,
+                    ..Default::default()
+                },
+                SunSetupError::ZeroDayLenght,
+            ),
+            (
+                SunSetup {
+                    latitude: 91.,
+                    ..Default::default()
+                },
+                SunSetupError::Latitude(91.),
+            ),
+            (
+                SunSetup {
+                    solar_constant: -1.,
+                    ..Default::default()
+                },
+                SunSetupError::NegativeSolarConstant(-1.),
+            ),
+            (
+                SunSetup {
+                    ambient: 101.,
+                    ..Default::default()
+                },
+                SunSetupError::Ambient(101.),
+            ),
+            (
+                SunSetup {
+                    moon_fraction: -0.1,
+                    ..Default::default()
+                },
+                SunSetupError::MoonFraction(-0.1),
+            ),
+            (
+                SunSetup {
+                    sun_angular_radius: 90.,
+                    ..Default::default()
+                },
+                SunSetupError::SunAngularRadius(90.),
+            ),
+            (
+                SunSetup {
+                    turbidity: -0.5,
+                    ..Default::default()
+                },
+                SunSetupError::NegativeTurbidity(-0.5),
+            ),
+        ] {
+            assert_eq!(Illumination::new(setup).unwrap_err(), error);
+        }
+        // NaN does not compare equal, so it is matched
+        let nan = Illumination::new(SunSetup {
+            turbidity: f64::NAN,
+            ..Default::default()
+        });
+        assert!(matches!(nan, Err(SunSetupError::NegativeTurbidity(t)) if t.is_nan()));
+    }
+
+    #[test]
+    fn sunset_is_redder() {
+        let illumination = Illumination::new(SunSetup {
+            latitude: 0.,
+            ..Default::default()
+        })
+        .unwrap();
+        let map = Field::new_filled(8., 8., 1., 0.).unwrap();
+        let redness = |c: &Vector3<f64>| c.x / c.z;
+
+        let noon = illumination.illuminate_rgb(&map, Time::ZERO);
+        let noon = noon.iter_vertices().next().unwrap().1;
+        assert!((redness(noon) - 1.).abs() < 0.05);
+
+        let sunset = illumination.illuminate_rgb(&map, Time::ZERO + Duration::HOUR * 5);
+        let sunset = sunset.iter_vertices().next().unwrap().1;
+        assert!(redness(sunset) > redness(noon) + 0.1);
+    }
+
+    #[test]
+    fn atmospheric_extinction() {
+        let setup = SunSetup {
+            latitude: 0.,
+            ..Default::default()
+        };
+        let clear = Illumination::new(setup).unwrap();
+        let hazy = Illumination::new(SunSetup {
+            turbidity: 0.2,
+            ..setup
+        })
+        .unwrap();
+
+        let noon = Time::ZERO;
+        assert_eq!(clear.direct(noon), hazy.direct(noon));
+
+        let sunset = Time::ZERO + Duration::HOUR * 5 + Duration::MINUTE * 50;
+        assert!(hazy.direct(sunset).unwrap().1 < clear.direct(sunset).unwrap().1);
+    }
+
+    #[test]
+    fn prebuilt_raycaster() {
+        let map =
+            Field::new_from_fun(8., 8., 0.5, |p| (p.x * 1.3).sin() + (p.y * 0.7).cos()).unwrap();
+        let time = Time::ZERO + Duration::HOUR * 4;
+        let illumination = Illumination::new(SunSetup::default()).unwrap();
 
         let caster = map.raycaster(Default::default());
+        let direct = illumination.illuminate(&map, time).into_grid();
+        let prebuilt = illumination.illuminate_with(&caster, time).into_grid();
+        assert_eq!(direct.flatten(), prebuilt.flatten());
+    }
 
-        map.clone().map_with_coords(|pos, height| {
-            // check if the sun is visible
-            let direct_sun_energy = if caster
-                .cast(
-                    point![pos.x, pos.y, height] + sun_pos.into_inner() * map.res() * 0.001,
-                    sun_pos.into_inner(),
-                )
-                .is_none()
-            {
-                // directly illuminated by the sun
-                map.normal(pos).dot(&energy_flux)
-            } else {
-                // sun is covered
-                0.
-            };
-
-            ambient + direct_sun_energy
+    #[test]
+    fn flat_panel() {
+        let map = Field::new_filled(8., 8., 1., 0.).unwrap();
+        let time = Time::ZERO + Duration::HOUR * 2;
+        let illumination = Illumination::new(SunSetup::default()).unwrap();
+
+        let caster = map.raycaster(Default::default());
+        let terrain = illumination
+            .illuminate_with(&caster, time)
+            .value(point![3., 3.]);
+        let panel =
+            illumination.flux_on_surface(point![3., 3., 0.], Vector3::z_axis(), time, &caster);
+        assert!((panel - terrain).abs() < 1e-9);
+
+        // a panel facing down gets only the ambient light
+        let ambient = LightSource::ambient(&illumination, time);
+        let flipped =
+            illumination.flux_on_surface(point![3., 3., 0.5], -Vector3::z_axis(), time, &caster);
+        assert!(ambient > 0.);
+        assert_eq!(flipped, ambient);
+    }
+
+    #[test]
+    fn lit_at_noon() {
+        let map = Field::new_filled(8., 8., 1., 0.).unwrap();
+        let illumination = Illumination::new(SunSetup::default()).unwrap();
+        let caster = map.raycaster(Default::default());
+
+        let noon = Time::ZERO;
+        let midnight = Time::ZERO + SunSetup::default().day_lenght / 2;
+        assert!(illumination.is_lit(&caster, point![3., 3.], 0., noon));
+        assert!(!illumination.is_lit(&caster, point![3., 3.], 0., midnight));
+    }
+
+    #[test]
+    fn shadow_mask() {
+        let map = Field::new_filled(8., 8., 1., 0.).unwrap();
+        let illumination = Illumination::new(SunSetup::default()).unwrap();
+
+        let noon = illumination.shadow_map(&map, Time::ZERO);
+        assert!(noon.as_grid().iter().all(|&shadow| !shadow));
+        let midnight = Time::ZERO + SunSetup::default().day_lenght / 2;
+        let midnight = illumination.shadow_map(&map, midnight);
+        assert!(midnight.as_grid().iter().all(|&shadow| shadow));
+    }
+
+    #[test]
+    fn sky_occlusion() {
+        let setup = SunSetup {
+            sky_occlusion_samples: 32,
+            ..Default::default()
+        };
+        let occluded = Illumination::new(setup).unwrap();
+        let plain = Illumination::new(SunSetup::default()).unwrap();
+        let time = Time::ZERO + Duration::HOUR * 3;
+
+        // on a flat map the whole sky is visible
+        let map = Field::new_filled(8., 8., 1., 0.).unwrap();
+        let a = occluded.illuminate(&map, time).into_grid();
+        let b = plain.illuminate(&map, time).into_grid();
+        for (a, b) in a.iter().zip(b.iter()) {
+            assert!((a - b).abs() < 1e-9);
+        }
+
+        // at night, the bottom of a pit sees less sky than its rim
+        let pit = Field::new_from_fun(8., 8., 0.5, |p| {
+            ((p.x - 4.).powi(2) + (p.y - 4.).powi(2)).sqrt().min(2.) * 3.
         })
+        .unwrap();
+        let moonlit = Illumination::new(SunSetup {
+            moon_fraction: 0.1,
+            ..setup
+        })
+        .unwrap();
+        let midnight = Time::ZERO + setup.day_lenght / 2;
+        let night = moonlit.illuminate(&pit, midnight);
+        assert!(night.value(point![4., 4.]) < night.value(point![0.5, 0.5]));
+    }
+
+    #[test]
+    fn polar_midnight_sun() {
+        let map = Field::new_filled(8., 8., 1., 0.).unwrap();
+        let setup = SunSetup {
+            latitude: 80.,
+            axial_tilt: 23.44,
+            season_phase: 90.,
+            ambient: 0.,
+            sun_model: SunModel::Spherical,
+            ..Default::default()
+        };
+        let sun = Illumination::new(setup).unwrap();
+        let midnight = Time::ZERO + setup.day_lenght / 2;
+        assert!(sun.sun_position(midnight).z > 0.);
+        assert!(*sun.illuminate(&map, midnight).min_by(f64::total_cmp) > 0.);
+
+        // the same day is identical in the two models
+        let planar = Illumination::new(SunSetup {
+            sun_model: SunModel::Planar,
+            ..setup
+        })
+        .unwrap();
+        let (a, b) = (planar.sun_position(midnight), sun.sun_position(midnight));
+        assert!((a.into_inner() - b.into_inner()).norm() < 1e-3);
+
+        // half a year later, it is polar night
+        let winter_midnight = midnight + setup.day_lenght * (365 / 2);
+        assert!(sun.sun_position(winter_midnight).z < 0.);
+        assert!(planar.sun_position(winter_midnight).z > 0.);
+    }
+
+    #[test]
+    fn seasons_at_equator() {
+        let noon_at = |season_phase| {
+            Illumination::new(SunSetup {
+                latitude: 0.,
+                axial_tilt: 23.44,
+                season_phase,
+                ..Default::default()
+            })
+            .unwrap()
+            .sun_position(Time::ZERO)
+        };
+        // the noon sun is north of the zenith in summer and south of it in winter
+        let summer = noon_at(90.);
+        let winter = noon_at(270.);
+        assert!(summer.y > 0.);
+        assert!(winter.y < 0.);
+        assert!((summer.z - winter.z).abs() < 1e-12);
     }
 }
@@ -1,4 +1,10 @@
 //! Implement time for the simulation, with 1/1024 of a second accurancy
+//!
+//! # Features
+//! - `humanized` (default): the [`humanized`] module, to display, parse and serialize times
+//!   as readable strings like `1d 2h`. Without it the crate does not depend on `serde_with`,
+//!   while the serde derives keep working.
+//! - `chrono`: conversions to and from the `chrono` types, in [`chrono_interop`].
 
 use std::ops::{
     Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Rem, RemAssign, Sub, SubAssign,
@@ -9,7 +15,12 @@ use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 /// A positive difference between two points in time
+///
+/// It is serialized as the integer number of 1/1024 s ticks, and negative values are rejected
+/// when deserializing. To get a readable string in human-readable formats annotate the field
+/// with `#[serde_as(as = "sim_time::humanized::HumanizeIfNeeded<Duration>")]`.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(try_from = "TimeDelta", into = "TimeDelta")]
 pub struct Duration(TimeDelta);
 
 impl Duration {
@@ -27,6 +38,40 @@ impl Duration {
     pub fn as_time_delta(self) -> TimeDelta {
         self.into()
     }
+
+    /// Build a duration from a number of seconds, rounding to the nearest tick
+    ///
+    /// Precision below 1/1024 of a second is lost.
+    ///
+    /// # Panics
+    /// Panics if the number of seconds is negative
+    pub fn from_secs_f64(secs: f64) -> Self {
+        assert!(secs >= 0., "Duration must be built from a positive number");
+        Self(TimeDelta::from_secs_f64(secs))
+    }
+
+    /// Build a duration from a number of milliseconds, rounding to the nearest tick
+    ///
+    /// Precision below 1/1024 of a second is lost. Values out of range saturate to
+    /// [`Duration::MAX`].
+    pub fn from_millis(millis: u64) -> Self {
+        let ticks = (millis as i128 * TimeDelta::SECOND.0 as i128 + 500) / 1000;
+        Self(TimeDelta(i64::try_from(ticks).unwrap_or(i64::MAX)))
+    }
+
+    pub fn as_secs_f64(self) -> f64 {
+        self.0.as_secs_f64()
+    }
+
+    /// How many whole `rhs` fit in this duration, and what is left
+    ///
+    /// # Panics
+    /// Panics if `rhs` is [`Duration::ZERO`]
+    pub fn div_rem(self, rhs: Duration) -> (u64, Duration) {
+        assert!(rhs != Duration::ZERO, "Division of a duration by zero");
+        let (a, b) = (self.0 .0 as u64, rhs.0 .0 as u64);
+        (a / b, Duration(TimeDelta((a % b) as i64)))
+    }
 }
 
 #[derive(Debug, Clone, Copy, Error)]
@@ -131,6 +176,16 @@ impl DivAssign<u64> for Duration {
         *self = *self / rhs
     }
 }
+/// Ratio between two durations
+///
+/// Dividing by [`Duration::ZERO`] gives infinity, or NaN if both are zero, as in IEEE 754
+impl Div for Duration {
+    type Output = f64;
+
+    fn div(self, rhs: Self) -> Self::Output {
+        self.0 / rhs.0
+    }
+}
 
 impl Rem for Duration {
     type Output = Duration;
@@ -148,8 +203,96 @@ impl<'a> Arbitrary<'a> for Duration {
     }
 }
 
+/// A strictly positive difference between two points in time
+///
+/// Useful where a zero delay would be a bug, as when rescheduling something periodically
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(try_from = "Duration", into = "Duration")]
+pub struct StrictlyPositiveDuration(Duration);
+
+impl StrictlyPositiveDuration {
+    pub const EPSILON: StrictlyPositiveDuration = StrictlyPositiveDuration(Duration::EPSILON);
+    pub const SECOND: StrictlyPositiveDuration = StrictlyPositiveDuration(Duration::SECOND);
+    pub const MINUTE: StrictlyPositiveDuration = StrictlyPositiveDuration(Duration::MINUTE);
+    pub const HOUR: StrictlyPositiveDuration = StrictlyPositiveDuration(Duration::HOUR);
+    pub const DAY: StrictlyPositiveDuration = StrictlyPositiveDuration(Duration::DAY);
+    pub const YEAR: StrictlyPositiveDuration = StrictlyPositiveDuration(Duration::YEAR);
+
+    pub const MIN: StrictlyPositiveDuration = StrictlyPositiveDuration(Duration::EPSILON);
+    pub const MAX: StrictlyPositiveDuration = StrictlyPositiveDuration(Duration::MAX);
+
+    pub fn as_duration(self) -> Duration {
+        self.0
+    }
+
+    pub fn as_time_delta(self) -> TimeDelta {
+        self.0.into()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+/// Time delta that is not strictly positive converted to a strictly positive duration
+pub enum NotPositiveTimeDelta {
+    #[error("Zero time delta converted to strictly positive duration")]
+    Zero,
+    #[error("Negative time delta {0:?} converted to strictly positive duration")]
+    Negative(TimeDelta),
+}
+
+impl TryFrom<TimeDelta> for StrictlyPositiveDuration {
+    type Error = NotPositiveTimeDelta;
+
+    fn try_from(value: TimeDelta) -> Result<Self, Self::Error> {
+        match value.cmp(&TimeDelta::ZERO) {
+            std::cmp::Ordering::Greater => Ok(Self(Duration(value))),
+            std::cmp::Ordering::Equal => Err(NotPositiveTimeDelta::Zero),
+            std::cmp::Ordering::Less => Err(NotPositiveTimeDelta::Negative(value)),
+        }
+    }
+}
+
+impl TryFrom<Duration> for StrictlyPositiveDuration {
+    type Error = NotPositiveTimeDelta;
+
+    fn try_from(value: Duration) -> Result<Self, Self::Error> {
+        value.as_time_delta().try_into()
+    }
+}
+
+impl From<StrictlyPositiveDuration> for Duration {
+    fn from(value: StrictlyPositiveDuration) -> Self {
+        value.0
+    }
+}
+impl From<StrictlyPositiveDuration> for TimeDelta {
+    fn from(value: StrictlyPositiveDuration) -> Self {
+        value.0.into()
+    }
+}
+
+impl Add<StrictlyPositiveDuration> for Time {
+    type Output = Time;
+
+    fn add(self, rhs: StrictlyPositiveDuration) -> Self::Output {
+        self + rhs.0
+    }
+}
+
+/// Units used to display time deltas, from the largest
+const UNITS: [(TimeDelta, &str); 5] = [
+    (TimeDelta::YEAR, "y"),
+    (TimeDelta::DAY, "d"),
+    (TimeDelta::HOUR, "h"),
+    (TimeDelta::MINUTE, "m"),
+    (TimeDelta::SECOND, "s"),
+];
+
 /// A difference between two point in time
+///
+/// It is serialized as the integer number of 1/1024 s ticks, see [`Duration`] for the
+/// humanized alternative.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(transparent)]
 pub struct TimeDelta(i64);
 
 impl TimeDelta {
@@ -164,6 +307,33 @@ impl TimeDelta {
     pub const MIN: TimeDelta = TimeDelta(i64::MIN);
     pub const MAX: TimeDelta = TimeDelta(i64::MAX);
 
+    /// Build a time delta from a number of seconds, rounding to the nearest tick
+    ///
+    /// Precision below 1/1024 of a second is lost. Values out of range saturate to
+    /// [`TimeDelta::MIN`] and [`TimeDelta::MAX`], and NaN gives [`TimeDelta::ZERO`]: use
+    /// [`TimeDelta::try_from_secs_f64`] when the input is not trusted.
+    pub fn from_secs_f64(secs: f64) -> Self {
+        Self((secs * Self::SECOND.0 as f64).round() as i64)
+    }
+
+    /// Build a time delta from a number of seconds, rounding to the nearest tick
+    ///
+    /// Fails if `secs` is NaN, or the result does not fit in a time delta, infinities included.
+    pub fn try_from_secs_f64(secs: f64) -> Result<Self, TimeConversionError> {
+        if secs.is_nan() {
+            return Err(TimeConversionError::NaN);
+        }
+        let ticks = (secs * Self::SECOND.0 as f64).round();
+        if !(i64::MIN as f64..i64::MAX as f64).contains(&ticks) {
+            return Err(TimeConversionError::OutOfRange(secs));
+        }
+        Ok(Self(ticks as i64))
+    }
+
+    pub fn as_secs_f64(self) -> f64 {
+        self.div_f(Self::SECOND)
+    }
+
     pub fn div_f(self, rhs: Self) -> f64 {
         self.0 as f64 / rhs.0 as f64
     }
@@ -171,13 +341,134 @@ impl TimeDelta {
         self.0 / rhs.0
     }
 
+    /// Magnitude of the time delta
     pub fn abs(self) -> Duration {
         Duration(Self(self.0.abs()))
     }
 
+    /// Sign of the time delta: -1 if negative, 0 if zero, 1 if positive
+    pub fn signum(self) -> i64 {
+        self.0.signum()
+    }
+
+    pub fn is_zero(self) -> bool {
+        self == Self::ZERO
+    }
+
     pub fn rem_euclid(self, rhs: TimeDelta) -> Duration {
         Duration(Self(self.0.rem_euclid(rhs.0)))
     }
+
+    /// Compress or dilate the time delta by `factor`, rounding to the nearest tick
+    ///
+    /// Returns `None` if the result overflows or `factor` is not finite. Unlike the
+    /// multiplication by a float, that truncates and saturates, this is meant to remap
+    /// time for fast forwarding: scaling every delay by the same positive factor keeps the
+    /// order of the events.
+    pub fn checked_scale_time(self, factor: f64) -> Option<TimeDelta> {
+        let result = (self.0 as f64 * factor).round();
+        (factor.is_finite() && (i64::MIN as f64..i64::MAX as f64).contains(&result))
+            .then_some(Self(result as i64))
+    }
+
+    /// Compress or dilate the time delta by `factor`, rounding to the nearest tick
+    ///
+    /// # Panics
+    /// Panics if the result overflows or `factor` is not finite, see
+    /// [`TimeDelta::checked_scale_time`]
+    pub fn scale_time(self, factor: f64) -> TimeDelta {
+        self.checked_scale_time(factor)
+            .expect("Overflow when scaling time delta")
+    }
+
+    /// Hash of the time delta, the same across runs and platforms
+    ///
+    /// Meant to seed procedural generation: unlike the [`Hash`] implementation the result does
+    /// not depend on the hasher, so it can be stored and compared. It is not suitable for
+    /// security. The ticks are mixed with the SplitMix64 finalizer.
+    pub fn stable_hash(&self) -> u64 {
+        let mut z = (self.0 as u64).wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Round to the nearest multiple of `unit`
+    ///
+    /// Halfway cases are rounded away from zero. The sign of `unit` is ignored, also for
+    /// [`TimeDelta::MIN`]. Results out of range saturate to [`TimeDelta::MIN`] and
+    /// [`TimeDelta::MAX`], so near the ends the result may not be a multiple of `unit`.
+    ///
+    /// # Panics
+    /// Panics if `unit` is zero
+    pub fn round_to(self, unit: TimeDelta) -> TimeDelta {
+        // in 128 bits the magnitude of the unit and the rounded value cannot overflow
+        let (value, unit) = (self.0 as i128, (unit.0 as i128).abs());
+        let rem = value.rem_euclid(unit);
+        let floor = value - rem;
+        let rounded = if rem > unit - rem || (rem == unit - rem && value > 0) {
+            floor + unit
+        } else {
+            floor
+        };
+        Self(rounded.clamp(i64::MIN as i128, i64::MAX as i128) as i64)
+    }
+
+    /// The largest unit that fits in this time delta, and the time delta expressed in it
+    ///
+    /// At exact boundaries the larger unit wins, so `MINUTE * 60` is `("h", 1.)`.
+    /// Time deltas shorter than a second are expressed in seconds.
+    pub fn dominant_unit(self) -> (&'static str, f64) {
+        let (unit, unit_name) = UNITS
+            .into_iter()
+            .find(|(unit, _)| self.0.unsigned_abs() >= unit.0 as u64)
+            .unwrap_or(UNITS[UNITS.len() - 1]);
+        (unit_name, self.div_f(unit))
+    }
+
+    /// Calculate `self * factor + addend`, returning `None` if the result overflows
+    ///
+    /// The intermediate product is not truncated, so this succeeds whenever the final
+    /// result fits.
+    pub fn checked_mul_add(self, factor: i64, addend: TimeDelta) -> Option<TimeDelta> {
+        let result = self.0 as i128 * factor as i128 + addend.0 as i128;
+        i64::try_from(result).ok().map(Self)
+    }
+
+    /// Calculate `self * factor + addend`
+    ///
+    /// # Panics
+    /// Panics if the result overflows. The intermediate product can exceed the range
+    /// of a time delta, see [`TimeDelta::checked_mul_add`].
+    pub fn mul_add(self, factor: i64, addend: TimeDelta) -> TimeDelta {
+        self.checked_mul_add(factor, addend)
+            .expect("Overflow in time delta multiply-add")
+    }
+
+    /// Calculate `self * factor + addend`, rounding to the nearest tick
+    ///
+    /// The calculation is done in floating point, so precision is lost for results
+    /// larger than 2^53 ticks (about 278 years).
+    ///
+    /// # Panics
+    /// Panics if the result overflows
+    pub fn mul_add_f64(self, factor: f64, addend: TimeDelta) -> TimeDelta {
+        let result = (self.0 as f64).mul_add(factor, addend.0 as f64).round();
+        assert!(
+            (i64::MIN as f64..i64::MAX as f64).contains(&result),
+            "Overflow in time delta multiply-add"
+        );
+        Self(result as i64)
+    }
+}
+
+/// Number of seconds that cannot be converted to a time delta
+#[derive(Debug, Clone, Copy, PartialEq, Error)]
+pub enum TimeConversionError {
+    #[error("Cannot convert NaN seconds to a time delta")]
+    NaN,
+    #[error("{0} seconds are out of the range of a time delta")]
+    OutOfRange(f64),
 }
 
 impl From<Duration> for TimeDelta {
@@ -268,6 +559,10 @@ impl Mul<TimeDelta> for i64 {
     }
 }
 
+/// Multiplication by a float, truncating toward zero
+///
+/// Results out of range saturate to [`TimeDelta::MIN`] and [`TimeDelta::MAX`], and NaN gives
+/// [`TimeDelta::ZERO`]. See [`TimeDelta::checked_scale_time`] to detect them.
 impl Mul<f64> for TimeDelta {
     type Output = TimeDelta;
 
@@ -302,6 +597,17 @@ impl Div<i64> for TimeDelta {
     }
 }
 
+/// Ratio between two time deltas
+///
+/// Dividing by [`TimeDelta::ZERO`] gives an infinity, or NaN if both are zero, as in IEEE 754
+impl Div for TimeDelta {
+    type Output = f64;
+
+    fn div(self, rhs: Self) -> Self::Output {
+        self.div_f(rhs)
+    }
+}
+
 impl DivAssign<i64> for TimeDelta {
     fn div_assign(&mut self, rhs: i64) {
         *self = *self / rhs
@@ -348,7 +654,10 @@ impl<'a> Arbitrary<'a> for TimeDelta {
 }
 
 /// A position in the simulation time
+///
+/// It is serialized as the integer number of 1/1024 s ticks from [`Time::ZERO`].
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(transparent)]
 pub struct Time(TimeDelta);
 
 impl Time {
@@ -364,9 +673,63 @@ impl Time {
     pub const MAX: Time = Time(TimeDelta::MAX);
 
     /// Add a time delta to this moment in time
+    ///
+    /// Overflowing panics in debug builds and wraps in release builds, as for integers.
+    /// Use one of the explicit variants to choose the behavior.
     pub fn offset(self, delta: TimeDelta) -> Time {
         Time(self.0.add(delta))
     }
+
+    /// Hash of the time, the same across runs and platforms
+    ///
+    /// This is the [`TimeDelta::stable_hash`] of the time elapsed from [`Time::ZERO`]
+    pub fn stable_hash(&self) -> u64 {
+        self.0.stable_hash()
+    }
+
+    /// Add a time delta, wrapping around the ends of time
+    ///
+    /// Going past [`Time::MAX`] restarts from [`Time::MIN`] and vice versa, in every build.
+    pub fn wrapping_offset(self, delta: TimeDelta) -> Time {
+        Time(TimeDelta(self.0 .0.wrapping_add(delta.0)))
+    }
+
+    /// Add a time delta, returning `None` if the result is past the ends of time
+    pub fn checked_offset(self, delta: TimeDelta) -> Option<Time> {
+        self.0 .0.checked_add(delta.0).map(|t| Time(TimeDelta(t)))
+    }
+
+    /// Add a time delta, stopping at [`Time::MIN`] and [`Time::MAX`]
+    pub fn saturating_offset(self, delta: TimeDelta) -> Time {
+        Time(TimeDelta(self.0 .0.saturating_add(delta.0)))
+    }
+
+    /// Duration elapsed from `earlier` to this moment
+    ///
+    /// Returns `None` if `earlier` is later than `self`, or if the difference does not fit
+    /// in a [`Duration`] (e.g. `Time::MAX.checked_duration_since(Time::MIN)`).
+    pub fn checked_duration_since(self, earlier: Time) -> Option<Duration> {
+        if self < earlier {
+            return None;
+        }
+        self.0
+             .0
+            .checked_sub(earlier.0 .0)
+            .map(|ticks| Duration(TimeDelta(ticks)))
+    }
+
+    /// Duration elapsed from `earlier` to this moment
+    ///
+    /// Returns [`Duration::ZERO`] if `earlier` is later than `self`, and [`Duration::MAX`]
+    /// if the difference does not fit in a [`Duration`].
+    pub fn saturating_duration_since(self, earlier: Time) -> Duration {
+        if self < earlier {
+            Duration::ZERO
+        } else {
+            self.checked_duration_since(earlier)
+                .unwrap_or(Duration::MAX)
+        }
+    }
 }
 
 impl Add<TimeDelta> for Time {
@@ -434,18 +797,189 @@ impl<'a> Arbitrary<'a> for Time {
     }
 }
 
+/// Iterator over the times from a start to an end, with a fixed step
+///
+/// The end is exclusive: the times are `start, start + step, ...` while they are before `end`.
+/// If `step` does not divide the interval evenly the last time is less than a step from `end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimeRange {
+    next: Option<Time>,
+    end: Time,
+    step: StrictlyPositiveDuration,
+}
+
+impl TimeRange {
+    pub fn new(start: Time, end: Time, step: StrictlyPositiveDuration) -> Self {
+        Self {
+            next: Some(start),
+            end,
+            step,
+        }
+    }
+}
+
+impl Iterator for TimeRange {
+    type Item = Time;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.next.filter(|&t| t < self.end)?;
+        let Time(TimeDelta(ticks)) = current;
+        self.next = ticks
+            .checked_add(self.step.as_time_delta().0)
+            .map(|ticks| Time(TimeDelta(ticks)));
+        Some(current)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = match self.next {
+            Some(t) if t < self.end => {
+                // the distance can exceed a duration, but always fits in 128 bits
+                let left = (self.end.0 .0 as i128 - t.0 .0 as i128) as u128;
+                let step = self.step.as_time_delta().0 as u128;
+                left.div_ceil(step)
+            }
+            _ => 0,
+        };
+        match usize::try_from(len) {
+            Ok(len) => (len, Some(len)),
+            Err(_) => (usize::MAX, None),
+        }
+    }
+}
+
+impl Time {
+    /// Latest multiple of `unit` not after this time
+    ///
+    /// The multiples are counted from [`Time::ZERO`], also for times before it, so
+    /// flooring `-90m` to hours gives `-2h`. The sign of `unit` is ignored.
+    /// Results before [`Time::MIN`] saturate to it.
+    ///
+    /// # Panics
+    /// Panics if `unit` is zero
+    pub fn floor_to(self, unit: TimeDelta) -> Time {
+        let (time, unit) = (self.0 .0 as i128, (unit.0 as i128).abs());
+        let floor = time - time.rem_euclid(unit);
+        Time(TimeDelta(floor.max(i64::MIN as i128) as i64))
+    }
+
+    /// Earliest multiple of `unit` not before this time
+    ///
+    /// See [`Time::floor_to`]. Results after [`Time::MAX`] saturate to it.
+    ///
+    /// # Panics
+    /// Panics if `unit` is zero
+    pub fn ceil_to(self, unit: TimeDelta) -> Time {
+        let (time, unit) = (self.0 .0 as i128, (unit.0 as i128).abs());
+        let ceil = time + (unit - time.rem_euclid(unit)) % unit;
+        Time(TimeDelta(ceil.min(i64::MAX as i128) as i64))
+    }
+
+    /// Iterate from this time up to `end` excluded, by `step`
+    pub fn step_by(self, end: Time, step: StrictlyPositiveDuration) -> TimeRange {
+        TimeRange::new(self, end, step)
+    }
+}
+
+/// Statistics over a set of durations
+///
+/// The samples are kept sorted, so that percentiles can be calculated at any time.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct DurationStats {
+    samples: Vec<Duration>,
+}
+
+impl DurationStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a sample
+    pub fn push(&mut self, sample: Duration) {
+        let idx = self.samples.partition_point(|&s| s <= sample);
+        self.samples.insert(idx, sample);
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        self.samples.first().copied()
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.samples.last().copied()
+    }
+
+    /// Mean of the samples, rounded to the nearest tick
+    ///
+    /// The sum is done in `i128`, so it cannot overflow.
+    pub fn mean(&self) -> Option<Duration> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let n = self.samples.len() as i128;
+        let sum: i128 = self.samples.iter().map(|s| s.0 .0 as i128).sum();
+        Some(Duration(TimeDelta(((sum + n / 2) / n) as i64)))
+    }
+
+    /// Percentile `p` of the samples, in [0, 100]
+    ///
+    /// Values between two samples are linearly interpolated and rounded to the nearest tick,
+    /// so the 50th percentile is the median.
+    ///
+    /// # Panics
+    /// Panics if `p` is outside [0, 100]
+    pub fn percentile(&self, p: f64) -> Option<Duration> {
+        assert!(
+            (0. ..=100.).contains(&p),
+            "Percentile {p} is outside [0, 100]"
+        );
+        let rank = p / 100. * (self.samples.len().checked_sub(1)?) as f64;
+        let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+        let (low_ticks, high_ticks) = (self.samples[low].0 .0, self.samples[high].0 .0);
+        let interpolated = low_ticks as f64 + (high_ticks - low_ticks) as f64 * (rank - low as f64);
+        Some(Duration(TimeDelta(interpolated.round() as i64)))
+    }
+
+    /// The samples, in increasing order
+    pub fn samples(&self) -> &[Duration] {
+        &self.samples
+    }
+}
+
+impl Extend<Duration> for DurationStats {
+    fn extend<I: IntoIterator<Item = Duration>>(&mut self, iter: I) {
+        self.samples.extend(iter);
+        self.samples.sort_unstable();
+    }
+}
+
+impl FromIterator<Duration> for DurationStats {
+    fn from_iter<I: IntoIterator<Item = Duration>>(iter: I) -> Self {
+        let mut stats = Self::new();
+        stats.extend(iter);
+        stats
+    }
+}
+
+#[cfg(feature = "humanized")]
 pub mod humanized {
     use std::fmt::{Debug, Display, Write};
+    use std::marker::PhantomData;
     use std::num::ParseFloatError;
     use std::str::FromStr;
 
-    use lazy_regex::{regex_captures, regex_is_match};
     use serde::Deserialize;
-    use serde::{de::Error as _, Deserializer, Serializer};
+    use serde::{de::Error as _, de::Visitor, Deserializer, Serializer};
     use serde_with::{DeserializeAs, IfIsHumanReadable, SerializeAs};
     use thiserror::Error;
 
-    use crate::NegativeTimeDelta;
+    use crate::{NegativeTimeDelta, UNITS};
 
     use super::{Duration, Time, TimeDelta};
 
@@ -477,43 +1011,206 @@ pub mod humanized {
     humanized_from_into! {TimeDelta}
     humanized_from_into! {Duration}
 
+    macro_rules! humanize_methods {
+        ($t:ty, $err:ty) => {
+            impl $t {
+                /// Format as a readable string, e.g. `1d 2h`, see [`Humanized`]
+                pub fn humanize(&self) -> String {
+                    Humanized(*self).to_string()
+                }
+
+                /// Parse from a readable string, e.g. `1d 2h`, see [`Humanized`]
+                pub fn parse_humanized(s: &str) -> Result<Self, $err> {
+                    s.parse::<Humanized<$t>>().map(Humanized::inner)
+                }
+            }
+        };
+    }
+    humanize_methods! {Time, ParseDurationError}
+    humanize_methods! {TimeDelta, ParseTimeDeltaError}
+    humanize_methods! {Duration, ParseDurationError}
+
+    /// Format the time delta as a sequence of numbers and units, e.g. `1d 2h 3.5s`
+    ///
+    /// The fill character of the formatter (`{:_<}`) is used to separate the components,
+    /// and the precision (`{:.2}`) applies to the seconds. Without flags the output can be
+    /// parsed back exactly.
     impl Display for Humanized<TimeDelta> {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            let mut t = self.0;
-            let mut spacing = false;
+            let mut separator = [0; 4];
+            let separator = f.fill().encode_utf8(&mut separator);
+            fmt_time_delta(
+                self.0,
+                f,
+                HumanFormat {
+                    separator,
+                    ..Default::default()
+                },
+            )
+        }
+    }
+
+    /// Options to format a humanized time delta, see [`Humanized::format_with`]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct HumanFormat<'a> {
+        /// Written between the components
+        pub separator: &'a str,
+        /// Written in place of the zero time delta
+        pub zero: &'a str,
+    }
 
-            if t == TimeDelta::ZERO {
-                return f.write_str("0");
+    impl Default for HumanFormat<'_> {
+        /// The format used by [`Display`], `1d 2h` and `0`
+        fn default() -> Self {
+            Self {
+                separator: " ",
+                zero: "0",
             }
+        }
+    }
 
-            for (unit, unit_name) in [
-                (TimeDelta::YEAR, "y"),
-                (TimeDelta::DAY, "d"),
-                (TimeDelta::HOUR, "h"),
-                (TimeDelta::MINUTE, "m"),
-            ] {
-                let n = t.div_i(unit);
-                t %= unit;
-                if n != 0 {
-                    if spacing {
-                        f.write_char(' ')?;
-                    }
-                    write!(f, "{n}{unit_name}")?;
-                    spacing = true;
-                }
+    /// A humanized time delta, formatted with custom [`HumanFormat`] options
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct FormatWith<'a> {
+        delta: TimeDelta,
+        format: HumanFormat<'a>,
+    }
+
+    impl Humanized<TimeDelta> {
+        /// Format with a custom separator and zero token
+        ///
+        /// The output can be parsed back only if the parser accepts the separator, as
+        /// whitespace or no separator at all, and the zero token is `0`.
+        /// The precision (`{:.2}`) still applies to the seconds.
+        pub fn format_with(self, format: HumanFormat<'_>) -> FormatWith<'_> {
+            FormatWith {
+                delta: self.0,
+                format,
             }
+        }
+    }
+
+    impl Humanized<Duration> {
+        /// Format with a custom separator and zero token
+        ///
+        /// See [`Humanized::<TimeDelta>::format_with`]
+        pub fn format_with(self, format: HumanFormat<'_>) -> FormatWith<'_> {
+            Humanized(TimeDelta::from(self.0)).format_with(format)
+        }
+    }
+
+    impl Display for FormatWith<'_> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            fmt_time_delta(self.delta, f, self.format)
+        }
+    }
+
+    fn fmt_time_delta(
+        mut t: TimeDelta,
+        f: &mut std::fmt::Formatter<'_>,
+        HumanFormat { separator, zero }: HumanFormat<'_>,
+    ) -> std::fmt::Result {
+        let mut spacing = false;
 
-            if t != TimeDelta::ZERO {
-                let secs = t.div_f(TimeDelta::SECOND);
+        if t == TimeDelta::ZERO {
+            return f.write_str(zero);
+        }
+
+        // seconds are printed last, with their fractional part
+        for &(unit, unit_name) in &UNITS[..UNITS.len() - 1] {
+            let n = t.div_i(unit);
+            t %= unit;
+            if n != 0 {
                 if spacing {
-                    f.write_char(' ')?;
+                    f.write_str(separator)?;
                 }
-                write!(f, "{secs}s")?;
+                write!(f, "{n}{unit_name}")?;
+                spacing = true;
+            }
+        }
+
+        if t != TimeDelta::ZERO {
+            let secs = t.div_f(TimeDelta::SECOND);
+            if spacing {
+                f.write_str(separator)?;
             }
+            match f.precision() {
+                Some(precision) => write!(f, "{secs:.precision$}s")?,
+                None => write!(f, "{secs}s")?,
+            }
+        }
+
+        Ok(())
+    }
 
+    impl Humanized<TimeDelta> {
+        /// View formatting each unit in its own column, to align multiple lines
+        ///
+        /// See [`Padded`].
+        pub fn padded(self) -> Padded {
+            Padded {
+                delta: self.0,
+                width: Padded::DEFAULT_WIDTH,
+            }
+        }
+    }
+
+    /// Format a time delta in fixed columns, e.g. `  1y   2d   3h`
+    ///
+    /// There is a column for each unit, from years to seconds, separated by a space.
+    /// Each column is `width` characters wide, unit included, and the values are right-aligned.
+    /// Zero units leave their column blank, so the columns of different time deltas line up;
+    /// values wider than the column push the following ones. The zero time delta is `0s`.
+    /// The precision (`{:.2}`) applies to the seconds, as for [`Humanized`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct Padded {
+        delta: TimeDelta,
+        width: usize,
+    }
+
+    impl Padded {
+        /// Width of the columns if not set, enough for up to 999 years
+        pub const DEFAULT_WIDTH: usize = 4;
+
+        /// Set the width of the columns
+        pub fn width(mut self, width: usize) -> Self {
+            self.width = width;
+            self
+        }
+    }
+
+    impl Display for Padded {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let width = self.width;
+            let mut t = self.delta;
+            for (i, &(unit, unit_name)) in UNITS.iter().enumerate() {
+                if i != 0 {
+                    f.write_char(' ')?;
+                }
+                let column = if unit == TimeDelta::SECOND {
+                    let secs = t.div_f(TimeDelta::SECOND);
+                    if t == TimeDelta::ZERO && self.delta != TimeDelta::ZERO {
+                        String::new()
+                    } else if let Some(precision) = f.precision() {
+                        format!("{secs:.precision$}s")
+                    } else {
+                        format!("{secs}s")
+                    }
+                } else {
+                    let n = t.div_i(unit);
+                    t %= unit;
+                    if n != 0 {
+                        format!("{n}{unit_name}")
+                    } else {
+                        String::new()
+                    }
+                };
+                write!(f, "{column:>width$}")?;
+            }
             Ok(())
         }
     }
+
     impl Display for Humanized<Duration> {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             Display::fmt(&Humanized(TimeDelta::from(self.0)), f)
@@ -531,49 +1228,106 @@ pub mod humanized {
         UnrecognizedTerm,
         #[error(transparent)]
         FloatConversionError(#[from] ParseFloatError),
+        #[error("Minutes and seconds of a clock time must be less than 60")]
+        ClockOutOfRange,
     }
     impl FromStr for Humanized<TimeDelta> {
         type Err = ParseTimeDeltaError;
 
-        fn from_str(mut s: &str) -> Result<Self, Self::Err> {
-            s = s.trim();
-            /* let ZERO_RE = lazy_regex!(
-                r"^(?:(?:(?:-?(?:\d+(?:\.\d*)?|\.\d+)(?:e\d+)?)\s*(?:y|d|h|m|s)\s*)+|(?:))$"gmi
-            ); */
-            if regex_is_match!(r"^-?(?:0+(?:\.0*)?|\.0+)(?:e\d+)?$"i, s) {
-                return Ok(Humanized(TimeDelta::ZERO));
-            }
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            parse_time_delta(s, false).map(Humanized)
+        }
+    }
 
-            let mut total = TimeDelta::ZERO;
-            while !s.is_empty() {
-                let Some((_, num, unit, rest)) = regex_captures!(
-                    r"^(-?(?:\d+(?:\.\d*)?|\.\d+)(?:e\d+)?)\s*([ydhms])\s*([^\s].*)?$",
-                    s
-                ) else {
-                    return Err(ParseTimeDeltaError::UnrecognizedTerm);
-                };
+    impl Humanized<TimeDelta> {
+        /// Parse a time delta, reading a last number with no unit as seconds
+        ///
+        /// `"3600"`, `"1h"` and `"59m 60"` are all the same time delta.
+        pub fn parse_default_seconds(s: &str) -> Result<Self, ParseTimeDeltaError> {
+            parse_time_delta(s, true).map(Humanized)
+        }
+    }
 
-                let unit = match unit {
-                    "y" => TimeDelta::YEAR,
-                    "d" => TimeDelta::DAY,
-                    "h" => TimeDelta::HOUR,
-                    "m" => TimeDelta::MINUTE,
-                    "s" => TimeDelta::SECOND,
-                    _ => unreachable!(),
-                };
+    fn parse_time_delta(
+        mut s: &str,
+        default_seconds: bool,
+    ) -> Result<TimeDelta, ParseTimeDeltaError> {
+        s = s.trim();
+        // a bare zero does not need a unit
+        if let Some((num, "")) = split_number(s, true) {
+            if num
+                .bytes()
+                .take_while(|b| !b.eq_ignore_ascii_case(&b'e'))
+                .all(|b| matches!(b, b'-' | b'.' | b'0'))
+            {
+                return Ok(TimeDelta::ZERO);
+            }
+        }
 
-                total += if let Ok(num) = num.parse::<i64>() {
-                    num * unit
-                } else {
-                    let num = num.parse::<f64>()?;
-                    num * unit
-                };
+        let mut total = TimeDelta::ZERO;
+        while !s.is_empty() {
+            let Some((num, rest)) = split_number(s, false) else {
+                return Err(ParseTimeDeltaError::UnrecognizedTerm);
+            };
+            let rest = rest.trim_start();
+            let (unit, rest) = match rest.chars().next() {
+                Some(unit @ ('y' | 'd' | 'h' | 'm' | 's')) => (Some(unit), rest[1..].trim_start()),
+                _ => (None, rest),
+            };
+
+            let unit = match unit {
+                Some('y') => TimeDelta::YEAR,
+                Some('d') => TimeDelta::DAY,
+                Some('h') => TimeDelta::HOUR,
+                Some('m') => TimeDelta::MINUTE,
+                Some('s') => TimeDelta::SECOND,
+                // only the last number can miss the unit
+                None if default_seconds && rest.is_empty() => TimeDelta::SECOND,
+                None => return Err(ParseTimeDeltaError::UnrecognizedTerm),
+                Some(_) => unreachable!(),
+            };
+
+            total += if let Ok(num) = num.parse::<i64>() {
+                num * unit
+            } else {
+                let num = num.parse::<f64>()?;
+                num * unit
+            };
+
+            s = rest
+        }
 
-                s = rest
+        Ok(total)
+    }
+
+    /// Split a leading number from a string
+    ///
+    /// The number is an optional minus sign, digits with an optional decimal part (or
+    /// only the decimal part) and an optional positive exponent, like `-1.5e3` or `.25`.
+    /// The exponent marker can also be uppercase if `any_case_exp` is set.
+    fn split_number(s: &str, any_case_exp: bool) -> Option<(&str, &str)> {
+        let bytes = s.as_bytes();
+        let digits_from = |i: usize| bytes[i..].iter().take_while(|b| b.is_ascii_digit()).count();
+
+        let mut i = usize::from(bytes.first() == Some(&b'-'));
+        let int_digits = digits_from(i);
+        i += int_digits;
+        if bytes.get(i) == Some(&b'.') {
+            let frac_digits = digits_from(i + 1);
+            if int_digits == 0 && frac_digits == 0 {
+                return None;
+            }
+            i += 1 + frac_digits;
+        } else if int_digits == 0 {
+            return None;
+        }
+        if let Some(&e) = bytes.get(i) {
+            let is_exp = e == b'e' || (any_case_exp && e == b'E');
+            if is_exp && digits_from(i + 1) > 0 {
+                i += 1 + digits_from(i + 1);
             }
-
-            Ok(Humanized(total))
         }
+        Some(s.split_at(i))
     }
 
     #[derive(Debug, Clone, Error)]
@@ -593,11 +1347,77 @@ pub mod humanized {
             Ok(Humanized(duration))
         }
     }
+    /// Parse a time, either as a time delta from [`Time::ZERO`] or as a clock time
+    ///
+    /// Strings containing a `:` are always read as a clock time, `HH:MM` or `HH:MM:SS`
+    /// with optional fractional seconds, so `14:30` is the same as `14h 30m`.
+    /// Clock times cannot be mixed with units: `14:30 1h` is rejected.
     impl FromStr for Humanized<Time> {
         type Err = ParseDurationError;
 
         fn from_str(s: &str) -> Result<Self, Self::Err> {
-            let delta = s.parse::<Humanized<TimeDelta>>()?.0;
+            let delta = match parse_clock(s)? {
+                Some(delta) => delta,
+                None => s.parse::<Humanized<TimeDelta>>()?.0,
+            };
+            let time = Time::ZERO + delta;
+            Ok(Humanized(time))
+        }
+    }
+
+    /// Parse a clock time `HH:MM[:SS[.sss]]`, or return `None` if `s` is not one
+    fn parse_clock(s: &str) -> Result<Option<TimeDelta>, ParseTimeDeltaError> {
+        let s = s.trim();
+        if !s.contains(':') {
+            return Ok(None);
+        }
+        let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+
+        let mut fields = s.split(':');
+        let hours = fields.next().filter(|h| !h.is_empty() && is_digits(h));
+        let minutes = fields.next().filter(|m| m.len() == 2 && is_digits(m));
+        let seconds = match fields.next() {
+            None => Some(""),
+            Some(seconds) => {
+                let (whole, frac) = seconds.split_once('.').unwrap_or((seconds, ""));
+                (whole.len() == 2 && is_digits(whole) && is_digits(frac)).then_some(seconds)
+            }
+        };
+        let (Some(hours), Some(minutes), Some(seconds), None) =
+            (hours, minutes, seconds, fields.next())
+        else {
+            return Err(ParseTimeDeltaError::UnrecognizedTerm);
+        };
+
+        let hours: i64 = hours
+            .parse()
+            .map_err(|_| ParseTimeDeltaError::UnrecognizedTerm)?;
+        let minutes: i64 = minutes.parse().unwrap();
+        let seconds: f64 = if seconds.is_empty() {
+            0.
+        } else {
+            seconds.parse()?
+        };
+        if minutes >= 60 || seconds >= 60. {
+            return Err(ParseTimeDeltaError::ClockOutOfRange);
+        }
+        Ok(Some(
+            TimeDelta::HOUR * hours + TimeDelta::MINUTE * minutes + TimeDelta::SECOND * seconds,
+        ))
+    }
+
+    impl Humanized<Duration> {
+        /// Parse a duration, reading a last number with no unit as seconds
+        pub fn parse_default_seconds(s: &str) -> Result<Self, ParseDurationError> {
+            let delta = Humanized::<TimeDelta>::parse_default_seconds(s)?.0;
+            let duration = delta.try_into()?;
+            Ok(Humanized(duration))
+        }
+    }
+    impl Humanized<Time> {
+        /// Parse a time, reading a last number with no unit as seconds
+        pub fn parse_default_seconds(s: &str) -> Result<Self, ParseDurationError> {
+            let delta = Humanized::<TimeDelta>::parse_default_seconds(s)?.0;
             let time = Time::ZERO + delta;
             Ok(Humanized(time))
         }
@@ -630,10 +1450,230 @@ pub mod humanized {
                 .map_err(D::Error::custom)
         }
     }
+    /// Use the humanized representation in human readable formats
+    ///
+    /// Every number must have a unit, see [`SecondsByDefaultIfNeeded`] to accept bare seconds
     pub type HumanizeIfNeeded<T> = IfIsHumanReadable<Humanized<T>>;
 
+    /// Like [`Humanized`], but a last number with no unit is read as seconds
+    ///
+    /// Plain numbers, not wrapped in a string, are also accepted as seconds.
+    /// Serialization is the same of [`Humanized`].
+    pub struct SecondsByDefault<T>(PhantomData<T>);
+
+    impl<T> SerializeAs<T> for SecondsByDefault<T>
+    where
+        Humanized<T>: SerializeAs<T>,
+    {
+        fn serialize_as<S>(source: &T, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            Humanized::<T>::serialize_as(source, serializer)
+        }
+    }
+
+    struct SecondsByDefaultVisitor<T>(fn(&str) -> Result<T, String>);
+
+    impl<'de, T> Visitor<'de> for SecondsByDefaultVisitor<T> {
+        type Value = T;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("a humanized time or a number of seconds")
+        }
+
+        fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            (self.0)(v).map_err(E::custom)
+        }
+        fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            self.visit_str(&v.to_string())
+        }
+        fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            self.visit_str(&v.to_string())
+        }
+        fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            self.visit_str(&v.to_string())
+        }
+    }
+
+    macro_rules! seconds_by_default {
+        ($t:ty) => {
+            impl<'de> DeserializeAs<'de, $t> for SecondsByDefault<$t> {
+                fn deserialize_as<D>(deserializer: D) -> Result<$t, D::Error>
+                where
+                    D: Deserializer<'de>,
+                {
+                    deserializer.deserialize_any(SecondsByDefaultVisitor(|s| {
+                        Humanized::<$t>::parse_default_seconds(s)
+                            .map(Humanized::inner)
+                            .map_err(|err| err.to_string())
+                    }))
+                }
+            }
+        };
+    }
+    seconds_by_default! {Time}
+    seconds_by_default! {TimeDelta}
+    seconds_by_default! {Duration}
+
+    /// Use the humanized representation in human readable formats, reading bare numbers as seconds
+    pub type SecondsByDefaultIfNeeded<T> = IfIsHumanReadable<SecondsByDefault<T>>;
+
     #[cfg(test)]
     mod tests {
+        use serde::Deserialize;
+        use serde_with::serde_as;
+
+        use crate::{
+            humanized::{HumanFormat, Humanized},
+            Duration, Time, TimeDelta,
+        };
+
+        #[test]
+        fn grammar() {
+            let parse = |s: &str| s.parse::<Humanized<TimeDelta>>().map(Humanized::inner);
+            for zero in ["", "0", "-0.0", " .00 ", "0E5", "0e12"] {
+                assert_eq!(parse(zero).unwrap(), TimeDelta::ZERO);
+            }
+            assert_eq!(parse("1.5h").unwrap(), TimeDelta::MINUTE * 90);
+            assert_eq!(parse(".5m").unwrap(), TimeDelta::SECOND * 30);
+            assert_eq!(parse("1.s").unwrap(), TimeDelta::SECOND);
+            assert_eq!(parse("1e2s").unwrap(), TimeDelta::SECOND * 100);
+            assert_eq!(parse("1h30m").unwrap(), TimeDelta::MINUTE * 90);
+            assert_eq!(parse(" -1 h  -30 m ").unwrap(), -TimeDelta::MINUTE * 90);
+            for invalid in ["h", "1", "1e", "1 2h", "1hm", "- 1h", ".h", "1E2s", "1x"] {
+                assert!(parse(invalid).is_err(), "{invalid:?} should not parse");
+            }
+        }
+
+        #[test]
+        fn clock_times() {
+            let parse = |s: &str| s.parse::<Humanized<Time>>().map(Humanized::inner);
+            let half_past_two = Time::ZERO + TimeDelta::HOUR * 14 + TimeDelta::MINUTE * 30;
+            assert_eq!(parse("14:30:00").unwrap(), half_past_two);
+            assert_eq!(parse("14:30").unwrap(), half_past_two);
+            assert_eq!(parse("14h 30m").unwrap(), half_past_two);
+            assert_eq!(
+                parse("14:30:00.5").unwrap(),
+                half_past_two + TimeDelta::SECOND / 2
+            );
+            assert!(parse("14:30 1h").is_err());
+            assert!(parse("14:75").is_err());
+            assert!(parse("14:30:60").is_err());
+        }
+
+        #[test]
+        fn format_flags() {
+            let delta = TimeDelta::HOUR + TimeDelta::MINUTE * 2 + TimeDelta::SECOND / 4;
+            assert_eq!(format!("{}", Humanized(delta)), "1h 2m 0.25s");
+            assert_eq!(format!("{:_>}", Humanized(delta)), "1h_2m_0.25s");
+            assert_eq!(format!("{:.1}", Humanized(delta)), "1h 2m 0.2s");
+            assert_eq!(format!("{:_>.3}", Humanized(delta)), "1h_2m_0.250s");
+        }
+
+        #[test]
+        fn padded_columns() {
+            let a = TimeDelta::YEAR + TimeDelta::DAY * 2 + TimeDelta::HOUR * 3;
+            let b = TimeDelta::DAY * 120 + TimeDelta::MINUTE * 5 + TimeDelta::SECOND * 7;
+            let a = Humanized(a).padded().to_string();
+            let b = Humanized(b).padded().to_string();
+            assert_eq!(a, "  1y   2d   3h          ");
+            assert_eq!(b, "     120d        5m   7s");
+            // the days end in the same column
+            assert_eq!(a.find("d "), b.find("d "));
+
+            assert_eq!(
+                Humanized(TimeDelta::SECOND * 3 / 2)
+                    .padded()
+                    .width(5)
+                    .to_string(),
+                format!("{}1.5s", " ".repeat(25))
+            );
+            assert_eq!(
+                Humanized(TimeDelta::ZERO).padded().to_string(),
+                format!("{}0s", " ".repeat(22))
+            );
+        }
+
+        #[test]
+        fn custom_format() {
+            let delta = TimeDelta::DAY + TimeDelta::HOUR * 2 + TimeDelta::SECOND / 2;
+            let csv = HumanFormat {
+                separator: "_",
+                zero: "-",
+            };
+            assert_eq!(Humanized(delta).format_with(csv).to_string(), "1d_2h_0.5s");
+            assert_eq!(Humanized(TimeDelta::ZERO).format_with(csv).to_string(), "-");
+            assert_eq!(
+                Humanized(delta)
+                    .format_with(HumanFormat::default())
+                    .to_string(),
+                Humanized(delta).to_string()
+            );
+
+            let compact = HumanFormat {
+                separator: "",
+                ..Default::default()
+            };
+            let formatted = Humanized(delta).format_with(compact).to_string();
+            assert_eq!(formatted, "1d2h0.5s");
+            assert_eq!(
+                formatted.parse::<Humanized<TimeDelta>>().unwrap().inner(),
+                delta
+            );
+        }
+
+        #[test]
+        fn humanize_methods() {
+            assert_eq!(Duration::HOUR.humanize(), "1h");
+            assert_eq!((-TimeDelta::MINUTE * 3).humanize(), "-3m");
+            assert_eq!((Time::ZERO + TimeDelta::DAY).humanize(), "1d");
+            assert_eq!(Duration::parse_humanized("1h").unwrap(), Duration::HOUR);
+            assert_eq!(Time::parse_humanized("01:00").unwrap(), Time::HOUR);
+            assert!(Duration::parse_humanized("-1h").is_err());
+            assert!(TimeDelta::parse_humanized("1 parsec").is_err());
+        }
+
+        #[test]
+        fn default_seconds() {
+            for s in ["3600", "3600s", "1h", "59m 60"] {
+                let parsed = Humanized::<TimeDelta>::parse_default_seconds(s).unwrap();
+                assert_eq!(parsed.inner(), TimeDelta::HOUR);
+            }
+            assert!("3600".parse::<Humanized<TimeDelta>>().is_err());
+            assert!(Humanized::<TimeDelta>::parse_default_seconds("60 1h").is_err());
+        }
+
+        #[test]
+        fn default_seconds_adapter() {
+            #[serde_as]
+            #[derive(Deserialize)]
+            struct Config {
+                #[serde_as(as = "crate::humanized::SecondsByDefaultIfNeeded<Duration>")]
+                day: Duration,
+            }
+
+            for json in [
+                r#"{"day": 86400}"#,
+                r#"{"day": "86400"}"#,
+                r#"{"day": "1d"}"#,
+            ] {
+                let config: Config = serde_json::from_str(json).unwrap();
+                assert_eq!(config.day, Duration::DAY);
+            }
+        }
 
         /// checks that displayed values are parsed to the same values
         mod parse_displayed {
@@ -679,3 +1719,529 @@ pub mod humanized {
         }
     }
 }
+
+/// Conversions to and from the [`chrono`](::chrono) types
+///
+/// [`TimeDelta`] has a resolution of 1/1024 of a second, while [`chrono::Duration`](::chrono::Duration)
+/// counts nanoseconds: conversions toward `chrono` round to the nearest nanosecond, and conversions
+/// from `chrono` round to the nearest tick.
+#[cfg(feature = "chrono")]
+pub mod chrono_interop {
+    use chrono::{DateTime, Utc};
+    use thiserror::Error;
+
+    use crate::{Time, TimeDelta};
+
+    const NANOS_PER_SEC: i128 = 1_000_000_000;
+
+    /// A `chrono` duration too large to be represented as a [`TimeDelta`]
+    #[derive(Debug, Clone, Copy, Error)]
+    #[error("Duration {0} is out of the range of a time delta")]
+    pub struct OutOfRange(chrono::Duration);
+
+    impl From<TimeDelta> for chrono::Duration {
+        /// Convert to a `chrono` duration, rounding to the nearest nanosecond
+        ///
+        /// This never overflows, as the range of `chrono` durations is wider than the one of
+        /// [`TimeDelta`].
+        fn from(value: TimeDelta) -> Self {
+            let secs = value.0.div_euclid(TimeDelta::SECOND.0);
+            let ticks = value.0.rem_euclid(TimeDelta::SECOND.0) as i128;
+            let nanos = (ticks * NANOS_PER_SEC + TimeDelta::SECOND.0 as i128 / 2)
+                / TimeDelta::SECOND.0 as i128;
+            chrono::Duration::seconds(secs) + chrono::Duration::nanoseconds(nanos as i64)
+        }
+    }
+
+    impl TryFrom<chrono::Duration> for TimeDelta {
+        type Error = OutOfRange;
+
+        /// Convert from a `chrono` duration, rounding to the nearest tick
+        ///
+        /// Fails if the duration is outside the range of [`TimeDelta`].
+        fn try_from(value: chrono::Duration) -> Result<Self, Self::Error> {
+            let secs = value.num_seconds() as i128;
+            let nanos = (value - chrono::Duration::seconds(value.num_seconds()))
+                .num_nanoseconds()
+                .expect("Subsecond part should fit in nanoseconds") as i128;
+            let ticks = secs * TimeDelta::SECOND.0 as i128
+                + (nanos * TimeDelta::SECOND.0 as i128 + NANOS_PER_SEC / 2)
+                    .div_euclid(NANOS_PER_SEC);
+            i64::try_from(ticks)
+                .map(TimeDelta)
+                .map_err(|_| OutOfRange(value))
+        }
+    }
+
+    impl Time {
+        /// Convert to a calendar date, treating [`Time::ZERO`] as `epoch`
+        ///
+        /// The time is rounded to the nearest nanosecond.
+        ///
+        /// # Panics
+        /// Panics if the resulting date is out of the range of [`DateTime`]
+        pub fn to_chrono(self, epoch: DateTime<Utc>) -> DateTime<Utc> {
+            epoch
+                .checked_add_signed((self - Time::ZERO).into())
+                .expect("Date out of range")
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use chrono::{DateTime, Utc};
+
+        use crate::{Time, TimeDelta};
+
+        #[test]
+        fn one_hour() {
+            assert_eq!(
+                chrono::Duration::from(TimeDelta::HOUR),
+                chrono::Duration::hours(1)
+            );
+            assert_eq!(
+                TimeDelta::try_from(chrono::Duration::hours(1)).unwrap(),
+                TimeDelta::HOUR
+            );
+            assert_eq!(
+                TimeDelta::try_from(chrono::Duration::hours(-1)).unwrap(),
+                -TimeDelta::HOUR
+            );
+        }
+
+        #[test]
+        fn rounded_to_tick() {
+            assert_eq!(
+                TimeDelta::try_from(chrono::Duration::milliseconds(1)).unwrap(),
+                TimeDelta::EPSILON
+            );
+            assert_eq!(
+                TimeDelta::try_from(chrono::Duration::milliseconds(-1)).unwrap(),
+                -TimeDelta::EPSILON
+            );
+            assert!(TimeDelta::try_from(chrono::Duration::max_value()).is_err());
+        }
+
+        #[test]
+        fn to_chrono() {
+            let epoch = DateTime::<Utc>::from_timestamp(1_700_000_000, 0).unwrap();
+            assert_eq!(Time::ZERO.to_chrono(epoch), epoch);
+            assert_eq!(
+                Time::HOUR.to_chrono(epoch),
+                epoch + chrono::Duration::hours(1)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Duration, DurationStats, NotPositiveTimeDelta, StrictlyPositiveDuration, Time,
+        TimeConversionError, TimeDelta,
+    };
+
+    #[test]
+    fn duration_since() {
+        assert_eq!(
+            Time::HOUR.checked_duration_since(Time::ZERO),
+            Some(Duration::HOUR)
+        );
+        assert_eq!(Time::ZERO.checked_duration_since(Time::HOUR), None);
+        assert_eq!(Time::MAX.checked_duration_since(Time::MIN), None);
+        assert_eq!(
+            Time::MAX.checked_duration_since(Time::ZERO),
+            Some(Duration::MAX)
+        );
+        assert_eq!(Time::ZERO.checked_duration_since(Time::MIN), None);
+        assert_eq!(
+            Time::EPSILON.checked_duration_since(Time::MIN + TimeDelta::EPSILON * 2),
+            Some(Duration::MAX)
+        );
+
+        assert_eq!(
+            Time::ZERO.saturating_duration_since(Time::HOUR),
+            Duration::ZERO
+        );
+        assert_eq!(
+            Time::MAX.saturating_duration_since(Time::MIN),
+            Duration::MAX
+        );
+        assert_eq!(
+            Time::HOUR.saturating_duration_since(Time::MINUTE),
+            Duration::MINUTE * 59
+        );
+    }
+
+    #[test]
+    fn strictly_positive() {
+        assert_eq!(
+            StrictlyPositiveDuration::try_from(TimeDelta::SECOND),
+            Ok(StrictlyPositiveDuration::SECOND)
+        );
+        assert_eq!(
+            StrictlyPositiveDuration::try_from(TimeDelta::ZERO),
+            Err(NotPositiveTimeDelta::Zero)
+        );
+        assert_eq!(
+            StrictlyPositiveDuration::try_from(-TimeDelta::SECOND),
+            Err(NotPositiveTimeDelta::Negative(-TimeDelta::SECOND))
+        );
+        assert_eq!(
+            StrictlyPositiveDuration::try_from(Duration::ZERO),
+            Err(NotPositiveTimeDelta::Zero)
+        );
+        assert!(serde_json::from_str::<StrictlyPositiveDuration>("0").is_err());
+        assert_eq!(
+            serde_json::from_str::<StrictlyPositiveDuration>("1024").unwrap(),
+            StrictlyPositiveDuration::SECOND
+        );
+    }
+
+    #[test]
+    fn time_range() {
+        let hours: Vec<_> = Time::ZERO
+            .step_by(Time::DAY, StrictlyPositiveDuration::HOUR)
+            .collect();
+        assert_eq!(hours.len(), 24);
+        assert_eq!(hours[0], Time::ZERO);
+        assert_eq!(hours[23], Time::ZERO + Duration::HOUR * 23);
+
+        // the last step falls short of the end
+        let range = Time::ZERO.step_by(Time::HOUR, (Duration::MINUTE * 7).try_into().unwrap());
+        assert_eq!(range.size_hint(), (9, Some(9)));
+        assert_eq!(range.last(), Some(Time::ZERO + Duration::MINUTE * 56));
+
+        assert_eq!(
+            Time::HOUR
+                .step_by(Time::ZERO, StrictlyPositiveDuration::SECOND)
+                .count(),
+            0
+        );
+        // the whole range of times is longer than a duration
+        let range = Time::MIN.step_by(Time::MAX, StrictlyPositiveDuration::HOUR);
+        let expected = (u64::MAX / (1024 * 3600) + 1) as usize;
+        assert_eq!(range.size_hint(), (expected, Some(expected)));
+        let mut exhausted = Time::MAX.step_by(Time::MAX, StrictlyPositiveDuration::HOUR);
+        assert_eq!(exhausted.size_hint(), (0, Some(0)));
+        assert_eq!(exhausted.next(), None);
+        let mut last =
+            (Time::MAX - Duration::HOUR / 2).step_by(Time::MAX, StrictlyPositiveDuration::HOUR);
+        assert_eq!(last.size_hint(), (1, Some(1)));
+        assert!(last.next().is_some());
+        assert_eq!(last.size_hint(), (0, Some(0)));
+
+        // no overflow near the end of time
+        assert_eq!(
+            (Time::MAX - Duration::SECOND)
+                .step_by(Time::MAX, StrictlyPositiveDuration::HOUR)
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let durations = [
+            Duration::ZERO,
+            Duration::HOUR + Duration::EPSILON,
+            Duration::MAX,
+        ];
+        for duration in durations {
+            let json = serde_json::to_string(&duration).unwrap();
+            assert_eq!(serde_json::from_str::<Duration>(&json).unwrap(), duration);
+
+            let mut cbor = vec![];
+            ciborium::into_writer(&duration, &mut cbor).unwrap();
+            assert_eq!(
+                ciborium::from_reader::<Duration, _>(cbor.as_slice()).unwrap(),
+                duration
+            );
+        }
+        assert_eq!(serde_json::to_string(&Duration::SECOND).unwrap(), "1024");
+        assert!(serde_json::from_str::<Duration>("-1").is_err());
+    }
+
+    #[test]
+    fn cbor_plain_integers() {
+        fn cbor<T: serde::Serialize>(value: &T) -> Vec<u8> {
+            let mut cbor = vec![];
+            ciborium::into_writer(value, &mut cbor).unwrap();
+            cbor
+        }
+        fn round_trip<T>(value: T, ticks: i64)
+        where
+            T: serde::Serialize + serde::de::DeserializeOwned + PartialEq + std::fmt::Debug,
+        {
+            let encoded = cbor(&value);
+            assert_eq!(encoded, cbor(&ticks));
+            assert_eq!(
+                ciborium::from_reader::<T, _>(encoded.as_slice()).unwrap(),
+                value
+            );
+        }
+
+        for ticks in [0, 1, 1024 * 3600, i64::MAX] {
+            round_trip(TimeDelta(ticks), ticks);
+            round_trip(Duration(TimeDelta(ticks)), ticks);
+            round_trip(Time(TimeDelta(ticks)), ticks);
+        }
+        for ticks in [-1, i64::MIN] {
+            round_trip(TimeDelta(ticks), ticks);
+            round_trip(Time(TimeDelta(ticks)), ticks);
+        }
+        round_trip(StrictlyPositiveDuration::HOUR, 1024 * 3600);
+    }
+
+    #[test]
+    fn dominant_unit() {
+        assert_eq!((TimeDelta::MINUTE * 90).dominant_unit(), ("h", 1.5));
+        assert_eq!((TimeDelta::MINUTE * 60).dominant_unit(), ("h", 1.));
+        assert_eq!((-TimeDelta::DAY * 3).dominant_unit(), ("d", -3.));
+        assert_eq!((TimeDelta::SECOND / 4).dominant_unit(), ("s", 0.25));
+        assert_eq!(TimeDelta::ZERO.dominant_unit(), ("s", 0.));
+    }
+
+    #[test]
+    fn duration_stats() {
+        let mut stats: DurationStats = [5, 1, 4, 2]
+            .into_iter()
+            .map(|n| Duration::SECOND * n)
+            .collect();
+        stats.push(Duration::SECOND * 3);
+        assert_eq!(stats.len(), 5);
+        assert_eq!(stats.min(), Some(Duration::SECOND));
+        assert_eq!(stats.max(), Some(Duration::SECOND * 5));
+        assert_eq!(stats.mean(), Some(Duration::SECOND * 3));
+        assert_eq!(stats.percentile(50.), Some(Duration::SECOND * 3));
+        assert_eq!(stats.percentile(0.), stats.min());
+        assert_eq!(stats.percentile(100.), stats.max());
+
+        stats.push(Duration::SECOND * 6);
+        assert_eq!(stats.percentile(50.), Some(Duration::SECOND * 7 / 2));
+        // no overflow when summing
+        let stats: DurationStats = [Duration::MAX; 3].into_iter().collect();
+        assert_eq!(stats.mean(), Some(Duration::MAX));
+        assert_eq!(DurationStats::new().percentile(50.), None);
+    }
+
+    #[test]
+    fn mul_add() {
+        assert_eq!(
+            TimeDelta::HOUR.mul_add(2, TimeDelta::MINUTE),
+            TimeDelta::HOUR * 2 + TimeDelta::MINUTE
+        );
+        // the product alone would overflow
+        assert_eq!(
+            TimeDelta::MAX.mul_add(2, TimeDelta::MIN),
+            TimeDelta(i64::MAX - 1)
+        );
+        assert_eq!(TimeDelta::MAX.checked_mul_add(2, TimeDelta::ZERO), None);
+        assert_eq!(
+            TimeDelta::HOUR.mul_add_f64(0.5, TimeDelta::MINUTE),
+            TimeDelta::MINUTE * 31
+        );
+    }
+
+    #[test]
+    fn ratios() {
+        assert_eq!(Duration::DAY / Duration::HOUR, 24.0);
+        assert_eq!(Duration::ZERO / Duration::SECOND, 0.0);
+        assert_eq!(-TimeDelta::MINUTE / TimeDelta::SECOND, -60.0);
+        assert_eq!(Duration::SECOND / Duration::ZERO, f64::INFINITY);
+        assert!((TimeDelta::ZERO / TimeDelta::ZERO).is_nan());
+    }
+
+    #[test]
+    fn align_to_units() {
+        let t = Time::ZERO + Duration::MINUTE * 90;
+        assert_eq!(t.floor_to(TimeDelta::HOUR), Time::HOUR);
+        assert_eq!(t.ceil_to(TimeDelta::HOUR), Time::HOUR + Duration::HOUR);
+        assert_eq!(Time::HOUR.floor_to(TimeDelta::HOUR), Time::HOUR);
+        assert_eq!(Time::HOUR.ceil_to(TimeDelta::HOUR), Time::HOUR);
+
+        let t = Time::ZERO - Duration::MINUTE * 90;
+        assert_eq!(t.floor_to(TimeDelta::HOUR), Time::ZERO - Duration::HOUR * 2);
+        assert_eq!(t.ceil_to(-TimeDelta::HOUR), Time::ZERO - Duration::HOUR);
+
+        assert_eq!(
+            (TimeDelta::MINUTE * 89).round_to(TimeDelta::HOUR),
+            TimeDelta::HOUR
+        );
+        assert_eq!(
+            (TimeDelta::MINUTE * 91).round_to(TimeDelta::HOUR),
+            TimeDelta::HOUR * 2
+        );
+        // halfway cases round away from zero
+        assert_eq!(
+            (TimeDelta::MINUTE * 90).round_to(TimeDelta::HOUR),
+            TimeDelta::HOUR * 2
+        );
+        assert_eq!(
+            (TimeDelta::MINUTE * -90).round_to(TimeDelta::HOUR),
+            TimeDelta::HOUR * -2
+        );
+        assert_eq!(
+            (TimeDelta::MINUTE * -89).round_to(TimeDelta::HOUR),
+            -TimeDelta::HOUR
+        );
+    }
+
+    #[test]
+    fn rounding_at_the_ends() {
+        // the nearest multiples are past the ends
+        assert_eq!(TimeDelta::MAX.round_to(TimeDelta::HOUR), TimeDelta::MAX);
+        assert_eq!(TimeDelta::MIN.round_to(TimeDelta::HOUR), TimeDelta::MIN);
+        assert_eq!(
+            (TimeDelta::MIN + TimeDelta::HOUR).round_to(-TimeDelta::HOUR),
+            (TimeDelta::MIN + TimeDelta::HOUR).round_to(TimeDelta::HOUR)
+        );
+        // the multiples of the longest unit are only the ends and zero
+        assert_eq!(TimeDelta::HOUR.round_to(TimeDelta::MIN), TimeDelta::ZERO);
+        assert_eq!(
+            (TimeDelta::MIN + TimeDelta::HOUR).round_to(TimeDelta::MIN),
+            TimeDelta::MIN
+        );
+        assert_eq!(
+            (TimeDelta::MAX - TimeDelta::HOUR).round_to(TimeDelta::MIN),
+            TimeDelta::MAX
+        );
+
+        assert_eq!(Time::MAX.ceil_to(TimeDelta::HOUR), Time::MAX);
+        assert_eq!(Time::MIN.floor_to(TimeDelta::HOUR), Time::MIN);
+        assert_eq!(Time::MIN.ceil_to(TimeDelta::MIN), Time::MIN);
+        assert_eq!(Time::MAX.floor_to(TimeDelta::MIN), Time::ZERO);
+        assert_eq!(Time::MIN.floor_to(-TimeDelta::EPSILON), Time::MIN);
+    }
+
+    #[test]
+    #[should_panic]
+    fn floor_to_zero() {
+        let _ = Time::HOUR.floor_to(TimeDelta::ZERO);
+    }
+
+    #[test]
+    fn offset_at_the_ends() {
+        assert_eq!(Time::MAX.wrapping_offset(TimeDelta::EPSILON), Time::MIN);
+        assert_eq!(Time::MIN.wrapping_offset(-TimeDelta::EPSILON), Time::MAX);
+        assert_eq!(
+            Time::MAX.wrapping_offset(TimeDelta::SECOND),
+            Time::MIN + TimeDelta::SECOND - TimeDelta::EPSILON
+        );
+        assert_eq!(Time::MAX.checked_offset(TimeDelta::EPSILON), None);
+        assert_eq!(Time::MIN.checked_offset(-TimeDelta::EPSILON), None);
+        assert_eq!(
+            Time::MAX.checked_offset(-TimeDelta::EPSILON),
+            Some(Time::MAX - TimeDelta::EPSILON)
+        );
+        assert_eq!(Time::MAX.saturating_offset(TimeDelta::HOUR), Time::MAX);
+        assert_eq!(Time::MIN.saturating_offset(-TimeDelta::HOUR), Time::MIN);
+        assert_eq!(
+            Time::ZERO.saturating_offset(TimeDelta::HOUR),
+            Time::ZERO.wrapping_offset(TimeDelta::HOUR)
+        );
+    }
+
+    #[test]
+    fn stable_hash() {
+        let time = Time::ZERO + TimeDelta::HOUR * 7;
+        assert_eq!(time.stable_hash(), time.stable_hash());
+        assert_eq!(time.stable_hash(), (TimeDelta::HOUR * 7).stable_hash());
+        assert_ne!(
+            time.stable_hash(),
+            (time + TimeDelta::EPSILON).stable_hash()
+        );
+        // fixed across platforms and releases
+        assert_eq!(TimeDelta::ZERO.stable_hash(), 0xe220_a839_7b1d_cdaf);
+    }
+
+    #[test]
+    fn scale_time() {
+        assert_eq!(TimeDelta::HOUR.scale_time(2.), TimeDelta::HOUR * 2);
+        assert_eq!(TimeDelta::HOUR.scale_time(0.5), TimeDelta::MINUTE * 30);
+        assert_eq!(TimeDelta::HOUR.scale_time(-1.), -TimeDelta::HOUR);
+        // rounds instead of truncating
+        assert_eq!(TimeDelta::EPSILON.scale_time(0.75), TimeDelta::EPSILON);
+        assert_eq!(TimeDelta::MAX.checked_scale_time(2.), None);
+        assert_eq!(TimeDelta::HOUR.checked_scale_time(f64::NAN), None);
+        assert_eq!(TimeDelta::ZERO.checked_scale_time(f64::INFINITY), None);
+    }
+
+    #[test]
+    fn div_rem() {
+        assert_eq!(
+            Duration::HOUR.div_rem(Duration::MINUTE),
+            (60, Duration::ZERO)
+        );
+        assert_eq!(
+            (Duration::HOUR + Duration::SECOND).div_rem(Duration::MINUTE),
+            (60, Duration::SECOND)
+        );
+        assert_eq!(
+            Duration::SECOND.div_rem(Duration::MINUTE),
+            (0, Duration::SECOND)
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn div_rem_by_zero() {
+        let _ = Duration::HOUR.div_rem(Duration::ZERO);
+    }
+
+    #[test]
+    fn from_seconds() {
+        assert_eq!(Duration::from_millis(1000), Duration::SECOND);
+        assert_eq!(Duration::from_millis(250), Duration::SECOND / 4);
+        assert_eq!(Duration::from_secs_f64(60.), Duration::MINUTE);
+        assert_eq!(TimeDelta::from_secs_f64(-0.5), -TimeDelta::SECOND / 2);
+        // rounded to the nearest tick
+        assert_eq!(Duration::from_millis(1), Duration::EPSILON);
+        assert_eq!(Duration::from_millis(u64::MAX), Duration::MAX);
+        assert_eq!(Duration::HOUR.as_secs_f64(), 3600.);
+        assert_eq!((-TimeDelta::MINUTE).as_secs_f64(), -60.);
+    }
+
+    #[test]
+    fn try_from_seconds() {
+        assert_eq!(
+            TimeDelta::try_from_secs_f64(-90.),
+            Ok(-TimeDelta::MINUTE * 3 / 2)
+        );
+        assert_eq!(
+            TimeDelta::try_from_secs_f64(f64::NAN),
+            Err(TimeConversionError::NaN)
+        );
+        assert_eq!(
+            TimeDelta::try_from_secs_f64(f64::INFINITY),
+            Err(TimeConversionError::OutOfRange(f64::INFINITY))
+        );
+        assert!(TimeDelta::try_from_secs_f64(f64::NEG_INFINITY).is_err());
+        // 2^53 seconds are 2^63 ticks, one past the end
+        let limit = 2f64.powi(53);
+        assert_eq!(
+            TimeDelta::try_from_secs_f64(limit),
+            Err(TimeConversionError::OutOfRange(limit))
+        );
+        assert_eq!(TimeDelta::try_from_secs_f64(-limit), Ok(TimeDelta::MIN));
+
+        // the unchecked versions saturate
+        assert_eq!(TimeDelta::from_secs_f64(f64::INFINITY), TimeDelta::MAX);
+        assert_eq!(TimeDelta::from_secs_f64(f64::NAN), TimeDelta::ZERO);
+        assert_eq!(TimeDelta::HOUR * f64::NEG_INFINITY, TimeDelta::MIN);
+    }
+
+    #[test]
+    fn timedelta_sign() {
+        for (delta, signum, abs) in [
+            (TimeDelta::ZERO, 0, Duration::ZERO),
+            (TimeDelta::HOUR, 1, Duration::HOUR),
+            (-TimeDelta::EPSILON, -1, Duration::EPSILON),
+            (-TimeDelta::DAY, -1, Duration::DAY),
+        ] {
+            assert_eq!(delta.signum(), signum);
+            assert_eq!(delta.is_zero(), signum == 0);
+            assert_eq!(delta.abs(), abs);
+        }
+    }
+}